@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+
+use crate::components::{MainCamera, Player, Position};
+use crate::map::Map;
+use crate::{FONT_HEIGHT, FONT_WIDTH, UI_WIDTH, VIEW_HEIGHT, VIEW_WIDTH};
+
+/// Keeps the player in the middle of the view, stopping at the edges of the map.
+pub fn follow_player(
+    map_data: Res<Map>,
+    player_query: Query<&Position, (With<Player>, Changed<Position>)>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+    let position = match player_query.single() {
+        Ok(position) => position,
+        Err(_) => return,
+    };
+    let mut camera = camera_query
+        .single_mut()
+        .expect("There should only be one main camera");
+    center_on(&mut camera, &map_data, position);
+}
+
+/// Moves the camera so `position` is in the middle of the view, stopping at the edges of the map.
+pub fn center_on(camera: &mut Transform, map_data: &Map, position: &Position) {
+    let view_width = VIEW_WIDTH as f32 * FONT_WIDTH;
+    let view_height = VIEW_HEIGHT as f32 * FONT_HEIGHT;
+
+    // Tile x covers x * FONT_WIDTH up to the next tile
+    let map_left = (-map_data.width / 2) as f32 * FONT_WIDTH;
+    let map_right = (map_data.width - map_data.width / 2) as f32 * FONT_WIDTH;
+    let map_bottom = (-map_data.height / 2) as f32 * FONT_HEIGHT;
+    let map_top = (map_data.height - map_data.height / 2) as f32 * FONT_HEIGHT;
+
+    let player_x = (position.x as f32 + 0.5) * FONT_WIDTH;
+    let player_y = (position.y as f32 + 0.5) * FONT_HEIGHT;
+
+    let view_left = (player_x - view_width / 2.0)
+        .min(map_right - view_width)
+        .max(map_left);
+    let view_bottom = (player_y - view_height / 2.0)
+        .min(map_top - view_height)
+        .max(map_bottom);
+
+    // The sidebar covers the right of the window, so the camera sits right of the view's centre
+    camera.translation.x = view_left + (view_width + UI_WIDTH) / 2.0;
+    camera.translation.y = view_bottom + view_height / 2.0;
+}
+
+/// The map point under the mouse cursor, or `None` when the cursor is off the map or over the
+/// sidebar.
+pub fn cursor_tile(windows: &Windows, camera: &Transform, map_data: &Map) -> Option<(i32, i32)> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()?;
+    if cursor.x > window.width() - UI_WIDTH {
+        return None;
+    }
+
+    // The cursor is measured from the bottom left of the window, the camera from its centre
+    let world = camera.translation.truncate() + cursor - Vec2::new(window.width(), window.height()) / 2.0;
+    let point = ((world.x / FONT_WIDTH).floor() as i32, (world.y / FONT_HEIGHT).floor() as i32);
+    if map_data.in_bounds(point.0, point.1) {
+        Some(point)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use bevy_tilemap::prelude::*;
+
+use crate::components::{CombatStats, DefenseBonus, Equipped, MeleePowerBonus, Name, Player, Position, Render, SufferDamage};
+use crate::gamelog::MessageLog;
+
+/// Sent when something attacks whatever it bumped into.
+pub struct WantsToMelee {
+    pub attacker: Entity,
+    pub target: Entity,
+}
+
+/// Drains the events rather than reading them, as this runs in both the player's and the
+/// monsters' turn and each attack must only land once.
+pub fn melee_combat(
+    mut melee_events: ResMut<Events<WantsToMelee>>,
+    mut log: ResMut<MessageLog>,
+    stats_query: Query<(&CombatStats, &Name)>,
+    mut damage_query: Query<&mut SufferDamage>,
+    equipment_query: Query<(&Equipped, Option<&MeleePowerBonus>, Option<&DefenseBonus>)>,
+) {
+    for melee in melee_events.drain() {
+        let (attacker_stats, attacker_name) = match stats_query.get(melee.attacker) {
+            Ok(attacker) => attacker,
+            Err(_) => continue,
+        };
+        let (target_stats, target_name) = match stats_query.get(melee.target) {
+            Ok(target) => target,
+            Err(_) => continue,
+        };
+        if attacker_stats.hp <= 0 || target_stats.hp <= 0 {
+            continue;
+        }
+
+        let mut power = attacker_stats.power;
+        let mut defense = target_stats.defense;
+        for (equipped, power_bonus, defense_bonus) in equipment_query.iter() {
+            if equipped.owner == melee.attacker {
+                power += power_bonus.map_or(0, |bonus| bonus.power);
+            }
+            if equipped.owner == melee.target {
+                defense += defense_bonus.map_or(0, |bonus| bonus.defense);
+            }
+        }
+
+        let damage = (power - defense).max(0);
+        if damage == 0 {
+            log.add(format!("{} is unable to hurt {}.", attacker_name.name, target_name.name));
+        } else {
+            log.add(format!("{} hits {} for {} hp.", attacker_name.name, target_name.name, damage));
+            if let Ok(mut suffer_damage) = damage_query.get_mut(melee.target) {
+                suffer_damage.amount.push(damage);
+            }
+        }
+    }
+}
+
+/// Applies the damage everything took this turn.
+pub fn damage(mut log: ResMut<MessageLog>, mut query: Query<(&mut CombatStats, &mut SufferDamage, &Name, Option<&Player>)>) {
+    for (mut stats, mut suffer_damage, name, player) in query.iter_mut() {
+        if suffer_damage.amount.is_empty() {
+            continue;
+        }
+        let was_alive = stats.hp > 0;
+        stats.hp -= suffer_damage.amount.drain(..).sum::<i32>();
+        if was_alive && stats.hp <= 0 {
+            if player.is_some() {
+                log.add("You are dead!");
+            } else {
+                log.add(format!("{} is dead.", name.name));
+            }
+        }
+    }
+}
+
+/// Removes everything but the player that has run out of hp.
+pub fn delete_the_dead(
+    mut commands: Commands,
+    mut tilemap_query: Query<&mut Tilemap>,
+    query: Query<(Entity, &CombatStats, &Position, &Render), Without<Player>>,
+) {
+    let mut tilemap = tilemap_query
+        .single_mut()
+        .expect("There should only be one map");
+
+    for (entity, stats, position, render) in query.iter() {
+        if stats.hp > 0 {
+            continue;
+        }
+        tilemap
+            .clear_tile((position.x, position.y), render.sprite_order)
+            .unwrap();
+        commands.entity(entity).despawn();
+    }
+}
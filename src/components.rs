@@ -20,16 +20,139 @@ pub struct Player;
 #[derive(Bundle)]
 pub struct PlayerBundle {
     pub player: Player,
+    pub name: Name,
     pub position: Position,
     pub render: Render,
     pub viewshed: Viewshed,
+    pub combat_stats: CombatStats,
+    pub suffer_damage: SufferDamage,
+    pub energy: Energy,
+    pub speed: Speed,
 }
 
 #[derive(Bundle)]
 pub struct EnemyBundle {
+    pub monster: Monster,
+    pub name: Name,
     pub position: Position,
     pub render: Render,
     pub viewshed: Viewshed,
+    pub blocks_tile: BlocksTile,
+    pub combat_stats: CombatStats,
+    pub suffer_damage: SufferDamage,
+    pub energy: Energy,
+    pub speed: Speed,
+}
+
+#[derive(Bundle)]
+pub struct ItemBundle {
+    pub item: Item,
+    pub name: Name,
+    pub position: Position,
+    pub render: Render,
+}
+
+#[derive(Default)]
+pub struct Monster {
+    /// Where the player was when last in view, to head for after losing sight of them.
+    pub last_seen_player: Option<Position>,
+}
+
+pub struct CombatStats {
+    pub max_hp: i32,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+}
+
+/// Damage taken this turn, added up and applied at once by `combat_system::damage`.
+#[derive(Default)]
+pub struct SufferDamage {
+    pub amount: Vec<i32>,
+}
+
+/// Saved up towards acting. Whoever reaches `energy_system::ACTION_THRESHOLD` gets to act, and
+/// pays for it out of this.
+#[derive(Default)]
+pub struct Energy {
+    pub amount: i32,
+}
+
+/// How much energy something gains each tick of the scheduler. 100 is normal speed.
+pub struct Speed {
+    pub energy_per_tick: i32,
+}
+
+/// Something that can be picked up.
+pub struct Item;
+
+/// Carried by `owner` rather than lying on the level. Carried items have no `Position`.
+pub struct InBackpack {
+    pub owner: Entity,
+}
+
+/// Restores hp to whoever uses it.
+pub struct ProvidesHealing {
+    pub amount: i32,
+}
+
+/// Used up when used.
+pub struct Consumable;
+
+/// Hurts whatever it is used on.
+pub struct InflictsDamage {
+    pub damage: i32,
+}
+
+/// Affects everything within `radius` of the target that the blast can reach.
+pub struct AreaOfEffect {
+    pub radius: i32,
+}
+
+/// Confuses whatever it is used on for `turns` turns.
+pub struct Confusion {
+    pub turns: i32,
+}
+
+/// Used on a point picked with the mouse, up to `range` tiles away, rather than on the user.
+pub struct Ranged {
+    pub range: i32,
+}
+
+/// Stumbles in random directions instead of acting, for `turns` more turns.
+pub struct Confused {
+    pub turns: i32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum EquipmentSlot {
+    Melee,
+    Shield,
+}
+
+pub struct Equippable {
+    pub slot: EquipmentSlot,
+}
+
+/// Worn or wielded by `owner`. Equipped items stay in the owner's backpack.
+pub struct Equipped {
+    pub owner: Entity,
+    pub slot: EquipmentSlot,
+}
+
+/// Added to the power of whoever has it equipped.
+pub struct MeleePowerBonus {
+    pub power: i32,
+}
+
+/// Added to the defense of whoever has it equipped.
+pub struct DefenseBonus {
+    pub defense: i32,
+}
+
+/// What something is called, matching the name `spawner::named` spawns it by.
+pub struct Name {
+    pub name: String,
 }
 
 pub struct Viewshed {
@@ -40,4 +163,13 @@ pub struct Viewshed {
 
 pub struct BlocksTile;
 
-pub struct GameLog;
\ No newline at end of file
+pub struct GameLog;
+
+pub struct DepthDisplay;
+
+/// The inventory screen, despawned when it closes.
+pub struct InventoryWindow;
+
+pub struct InventoryText;
+
+pub struct MainCamera;
\ No newline at end of file
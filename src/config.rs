@@ -1,6 +1,10 @@
 use bevy::prelude::*;
 use serde::Deserialize;
 
+use crate::map_builders::{self, BuilderType, CorridorSettings, DlaSettings, DrunkardSettings, WfcSettings};
+use crate::rng::{GameRng, MapSeed};
+use crate::terrain::TerrainTable;
+
 #[derive(Deserialize)]
 pub struct InputConfig {
     pub up: KeyCode,
@@ -8,10 +12,78 @@ pub struct InputConfig {
     pub left: KeyCode,
     pub right: KeyCode,
     pub pick_up: KeyCode,
+    pub drop: KeyCode,
     pub inventory: KeyCode,
+    /// Keys for acting on the item picked in the inventory.
+    pub use_item: KeyCode,
+    pub equip: KeyCode,
+    pub inspect: KeyCode,
+    /// Backs out of the inventory.
+    pub cancel: KeyCode,
+    pub descend: KeyCode,
+    pub ascend: KeyCode,
+    pub close_door: KeyCode,
+    pub dump_map: KeyCode,
+    /// Enters and leaves the map editor.
+    pub editor: KeyCode,
+    pub editor_next_tile: KeyCode,
+    pub editor_next_spawn: KeyCode,
+    pub editor_save: KeyCode,
+    pub skip_playback: KeyCode,
+}
+
+#[derive(Deserialize)]
+pub struct MapGenConfig {
+    pub seed: Option<u64>,
+    /// Size of every level in tiles. Levels larger than the window scroll with the player.
+    pub width: i32,
+    pub height: i32,
+    /// Levels with fewer tiles reachable from the player start are rebuilt.
+    pub min_open_tiles: usize,
+    pub builder: BuilderType,
+    /// How the Simple and Bsp builders join their rooms.
+    pub corridors: CorridorSettings,
+    pub drunkard: DrunkardSettings,
+    pub dla: DlaSettings,
+    pub wfc: WfcSettings,
+    /// How many prefab vaults to try setting into each generated level.
+    pub max_vaults: i32,
+    /// Plays back each level being built before it can be played.
+    pub show_generation: bool,
 }
 
 pub fn open_config(mut commands: Commands) {
     let input_config: InputConfig = ron::from_str(&std::fs::read_to_string("assets/config/input.ron").unwrap()).unwrap();
     commands.insert_resource(input_config);
-}
\ No newline at end of file
+
+    let mut mapgen_config: MapGenConfig = ron::from_str(&std::fs::read_to_string("assets/config/mapgen.ron").unwrap()).unwrap();
+    // A seed given on the command line wins over the one in the config file
+    let seed = MapSeed(
+        arg_value("--seed")
+            .map(|seed| seed.parse().expect("--seed must be an unsigned integer"))
+            .or(mapgen_config.seed)
+            .unwrap_or_else(rand::random),
+    );
+    info!("Using seed {}", seed.0);
+    if let Some(name) = arg_value("--map") {
+        mapgen_config.builder = BuilderType::AsciiMap(name);
+    }
+    commands.insert_resource(seed);
+    commands.insert_resource(GameRng::from_seed(seed));
+    commands.insert_resource(mapgen_config);
+    commands.insert_resource(map_builders::load_prefabs("assets/prefabs"));
+
+    let terrain: TerrainTable = ron::from_str(&std::fs::read_to_string("assets/config/terrain.ron").unwrap()).unwrap();
+    commands.insert_resource(terrain);
+}
+
+/// Returns the value following `flag` on the command line, e.g. `--seed 42`.
+fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+    }
+    None
+}
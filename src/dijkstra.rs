@@ -0,0 +1,161 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::map::Map;
+use crate::terrain::TerrainTable;
+
+/// Flee maps scale the negated distances by this. Values above 1 let a fleeing monster step
+/// back towards its goals when that leads somewhere further away, instead of cornering itself.
+const FLEE_SCALE: f32 = 1.2;
+
+static DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+/// Distance from every tile of a `Map` to the nearest of a set of goal tiles, following
+/// 4-way movement and paying each tile's movement cost to step onto it.
+#[derive(Default)]
+pub struct DijkstraMap {
+    pub width: i32,
+    pub height: i32,
+    /// Indexed like `Map::tiles`. Tiles that can't reach a goal within `max_depth` are
+    /// `f32::MAX`.
+    pub values: Vec<f32>,
+    pub max_depth: f32,
+    /// Cost of stepping onto each tile, `None` where it can't be entered.
+    costs: Vec<Option<f32>>,
+}
+
+impl DijkstraMap {
+    /// Builds a map towards `goals` that goes around `Map::blocked` and pays the terrain's
+    /// movement costs.
+    pub fn new(map: &Map, terrain: &TerrainTable, goals: &[usize], max_depth: f32) -> Self {
+        Self::with_costs(map, goals, max_depth, |idx| {
+            if map.blocked[idx] {
+                None
+            } else {
+                Some(terrain.get(map.tiles[idx]).move_cost)
+            }
+        })
+    }
+
+    /// Builds a map towards `goals` where `cost` gives the price of stepping onto a tile, or
+    /// `None` if it can't be entered.
+    pub fn with_costs(map: &Map, goals: &[usize], max_depth: f32, cost: impl Fn(usize) -> Option<f32>) -> Self {
+        let mut dijkstra = Self {
+            width: map.width,
+            height: map.height,
+            values: vec![f32::MAX; map.tiles.len()],
+            max_depth,
+            costs: (0..map.tiles.len()).map(cost).collect(),
+        };
+        let seeds = goals.iter().map(|&goal| (goal, 0.0)).collect();
+        dijkstra.relax(seeds);
+        dijkstra
+    }
+
+    /// A map that leads away from this map's goals. Distances are negated and scaled, then
+    /// relaxed again, so following it downhill heads for places far from the goals rather
+    /// than into the nearest corner.
+    pub fn flee_map(&self) -> Self {
+        let mut flee = Self {
+            width: self.width,
+            height: self.height,
+            values: vec![f32::MAX; self.values.len()],
+            max_depth: self.max_depth,
+            costs: self.costs.clone(),
+        };
+        let seeds = self
+            .values
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| **value < f32::MAX)
+            .map(|(idx, value)| (idx, -value * FLEE_SCALE))
+            .collect();
+        flee.relax(seeds);
+        flee
+    }
+
+    /// The neighbour of `idx` with the lowest value, if it is lower than `idx` itself.
+    pub fn lowest_neighbour(&self, idx: usize) -> Option<usize> {
+        self.neighbours(idx)
+            .filter(|&neighbour| self.values[neighbour] < self.values[idx])
+            .min_by(|&a, &b| self.values[a].partial_cmp(&self.values[b]).unwrap())
+    }
+
+    /// The reachable tile with the highest value, limited to tiles `filter` accepts.
+    pub fn furthest(&self, filter: impl Fn(usize) -> bool) -> Option<usize> {
+        (0..self.values.len())
+            .filter(|&idx| self.values[idx] < f32::MAX && filter(idx))
+            .max_by(|&a, &b| self.values[a].partial_cmp(&self.values[b]).unwrap())
+    }
+
+    fn neighbours(&self, idx: usize) -> impl Iterator<Item = usize> {
+        let (width, height) = (self.width, self.height);
+        let x = idx as i32 % width;
+        let y = idx as i32 / width;
+        DIRECTIONS
+            .iter()
+            .map(move |(dx, dy)| (x + dx, y + dy))
+            .filter(move |&(nx, ny)| nx >= 0 && ny >= 0 && nx < width && ny < height)
+            .map(move |(nx, ny)| (ny * width + nx) as usize)
+    }
+
+    /// Lowers every value reachable from `seeds` to its shortest distance, stopping at
+    /// `max_depth`.
+    fn relax(&mut self, seeds: Vec<(usize, f32)>) {
+        let mut open = BinaryHeap::new();
+        for (idx, value) in seeds {
+            if value < self.values[idx] {
+                self.values[idx] = value;
+                open.push(OpenNode { value, idx });
+            }
+        }
+
+        while let Some(OpenNode { value, idx }) = open.pop() {
+            if value > self.values[idx] {
+                continue;
+            }
+            for neighbour in self.neighbours(idx).collect::<Vec<_>>() {
+                let cost = match self.costs[neighbour] {
+                    Some(cost) => cost,
+                    None => continue,
+                };
+                let next = value + cost;
+                if next <= self.max_depth && next < self.values[neighbour] {
+                    self.values[neighbour] = next;
+                    open.push(OpenNode { value: next, idx: neighbour });
+                }
+            }
+        }
+    }
+}
+
+/// Entry in a search's open set, ordered so a `BinaryHeap` pops the lowest value first.
+pub struct OpenNode {
+    pub value: f32,
+    pub idx: usize,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.value.partial_cmp(&self.value).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Distances to the player, rebuilt every turn so monsters can approach or flee without each
+/// running their own search.
+#[derive(Default)]
+pub struct PlayerDistanceMap(pub DijkstraMap);
@@ -0,0 +1,206 @@
+use bevy::prelude::*;
+use bevy_tilemap::prelude::*;
+
+use crate::camera_system::{center_on, cursor_tile};
+use crate::components::{MainCamera, Name, Player, Position, Viewshed};
+use crate::config::InputConfig;
+use crate::map::{Depth, Map, TileType};
+use crate::map_builders;
+use crate::map_system::hide_unrevealed;
+use crate::rng::MapSeed;
+use crate::terrain::TerrainTable;
+use crate::{GameState, FONT_HEIGHT, FONT_WIDTH};
+
+/// Tiles the brush cycles through, in order.
+const PALETTE: [TileType; 16] = [
+    TileType::Floor,
+    TileType::Wall,
+    TileType::ClosedDoor,
+    TileType::OpenDoor,
+    TileType::DownStairs,
+    TileType::UpStairs,
+    TileType::ShallowWater,
+    TileType::DeepWater,
+    TileType::Lava,
+    TileType::TallGrass,
+    TileType::Rubble,
+    TileType::Chasm,
+    TileType::Bookshelf,
+    TileType::Bed,
+    TileType::Altar,
+    TileType::Pillar,
+];
+/// Names that spawn markers can be placed for, as understood by `spawner::named`.
+const SPAWN_NAMES: [&str; 8] = [
+    "Goblin",
+    "Orc",
+    "Health Potion",
+    "Magic Missile Scroll",
+    "Fireball Scroll",
+    "Confusion Scroll",
+    "Dagger",
+    "Shield",
+];
+/// How many tiles the view moves per key press.
+const SCROLL_STEP: f32 = 4.0;
+
+/// What the editor has selected and placed. Markers are only written to saved maps; nothing is
+/// spawned for them while playing.
+#[derive(Default)]
+pub struct EditorState {
+    tile: usize,
+    spawn: usize,
+    markers: Vec<((i32, i32), String)>,
+    /// `Map::revealed_tiles` from before the editor showed the whole map.
+    revealed_tiles: Vec<bool>,
+}
+
+/// Shows the whole level so it can be edited.
+pub fn enter_editor(mut editor: ResMut<EditorState>, mut map_data: ResMut<Map>) {
+    info!(
+        "Editing: left click paints {:?}, right click places a {} marker",
+        PALETTE[editor.tile], SPAWN_NAMES[editor.spawn]
+    );
+    editor.revealed_tiles = map_data.revealed_tiles.clone();
+    for idx in 0..map_data.tiles.len() {
+        map_data.revealed_tiles[idx] = true;
+        map_data.visible_tiles[idx] = true;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn editor_input(
+    mut game_state: ResMut<State<GameState>>,
+    mut editor: ResMut<EditorState>,
+    keyboard_input: Res<Input<KeyCode>>,
+    input_config: Res<InputConfig>,
+    map_data: Res<Map>,
+    depth: Res<Depth>,
+    seed: Res<MapSeed>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+    player_query: Query<&Position, With<Player>>,
+    name_query: Query<(&Position, &Name), Without<Player>>,
+) {
+    let mut camera = camera_query
+        .single_mut()
+        .expect("There should only be one main camera");
+
+    for key in keyboard_input.get_just_pressed() {
+        if key == &input_config.editor {
+            game_state.set(GameState::PlayerTurn).unwrap();
+            return;
+        } else if key == &input_config.editor_next_tile {
+            editor.tile = (editor.tile + 1) % PALETTE.len();
+            info!("Painting {:?}", PALETTE[editor.tile]);
+        } else if key == &input_config.editor_next_spawn {
+            editor.spawn = (editor.spawn + 1) % SPAWN_NAMES.len();
+            info!("Placing {} markers", SPAWN_NAMES[editor.spawn]);
+        } else if key == &input_config.editor_save {
+            let player = player_query.single().expect("There should only be one player");
+            let spawns: Vec<_> = name_query
+                .iter()
+                .map(|(position, name)| ((position.x, position.y), name.name.clone()))
+                .chain(editor.markers.iter().cloned())
+                .collect();
+            let text = map_builders::to_ascii(&map_data, (player.x, player.y), &spawns);
+
+            let name = format!("edited-{}-{}", seed.0, depth.0);
+            std::fs::create_dir_all("assets/maps").unwrap();
+            std::fs::write(format!("assets/maps/{}.txt", name), text).expect("Unable to save map");
+            info!("Saved the level, play it with --map {}", name);
+        } else if key == &input_config.up {
+            camera.translation.y += SCROLL_STEP * FONT_HEIGHT;
+        } else if key == &input_config.down {
+            camera.translation.y -= SCROLL_STEP * FONT_HEIGHT;
+        } else if key == &input_config.left {
+            camera.translation.x -= SCROLL_STEP * FONT_WIDTH;
+        } else if key == &input_config.right {
+            camera.translation.x += SCROLL_STEP * FONT_WIDTH;
+        }
+    }
+}
+
+/// Paints the selected tile while the left button is held, and places or removes a spawn
+/// marker on a right click.
+pub fn paint(
+    mut editor: ResMut<EditorState>,
+    mut map_data: ResMut<Map>,
+    terrain: Res<TerrainTable>,
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+    mut tilemap_query: Query<&mut Tilemap>,
+) {
+    let camera = camera_query.single().expect("There should only be one main camera");
+    let point = match cursor_tile(&windows, camera, &map_data) {
+        Some(point) => point,
+        None => return,
+    };
+    let mut tilemap = tilemap_query
+        .single_mut()
+        .expect("There should only be one map");
+
+    if mouse_input.pressed(MouseButton::Left) {
+        let tile = PALETTE[editor.tile];
+        let idx = map_data.xy_idx(point.0, point.1);
+        if map_data.tiles[idx] != tile {
+            map_data.set_tile(idx, tile, &terrain);
+            let terrain_def = terrain.get(tile);
+            let (r, g, b) = terrain_def.color;
+            let mut map_tile = tilemap.get_tile_mut(point, 0).expect("Nonexistent Tile");
+            map_tile.index = terrain_def.glyph as usize;
+            map_tile.color = Color::rgb_linear(r, g, b);
+        }
+    }
+
+    if mouse_input.just_pressed(MouseButton::Right) {
+        if let Some(existing) = editor.markers.iter().position(|(marker, _)| *marker == point) {
+            editor.markers.remove(existing);
+            tilemap.clear_tile(point, 1).unwrap();
+        } else {
+            let name = SPAWN_NAMES[editor.spawn];
+            editor.markers.push((point, name.to_string()));
+            tilemap
+                .insert_tile(Tile {
+                    point,
+                    sprite_index: marker_glyph(name) as usize,
+                    sprite_order: 1,
+                    tint: Color::YELLOW,
+                })
+                .unwrap();
+        }
+    }
+}
+
+/// Hides the markers, puts back what the player had seen of the level and brings the view back
+/// to the player.
+pub fn exit_editor(
+    mut editor: ResMut<EditorState>,
+    mut map_data: ResMut<Map>,
+    mut tilemap_query: Query<&mut Tilemap>,
+    mut player_query: Query<(&Position, &mut Viewshed), With<Player>>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+) {
+    let mut tilemap = tilemap_query
+        .single_mut()
+        .expect("There should only be one map");
+    for (point, _) in editor.markers.drain(..) {
+        tilemap.clear_tile(point, 1).unwrap();
+    }
+
+    map_data.revealed_tiles = std::mem::take(&mut editor.revealed_tiles);
+    hide_unrevealed(&mut tilemap, &map_data);
+
+    let (position, mut viewshed) = player_query.single_mut().expect("There should only be one player");
+    viewshed.dirty = true;
+
+    // The camera only follows the player when they move, so it would stay wherever it was scrolled
+    let mut camera = camera_query
+        .single_mut()
+        .expect("There should only be one main camera");
+    center_on(&mut camera, &map_data, position);
+}
+
+fn marker_glyph(name: &str) -> char {
+    name.chars().next().unwrap_or('?').to_ascii_lowercase()
+}
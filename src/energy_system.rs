@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+
+use crate::components::{CombatStats, Energy, Player, Position, Speed};
+use crate::map::TileType;
+use crate::terrain::TerrainTable;
+
+/// Energy something needs before it can act.
+pub const ACTION_THRESHOLD: i32 = 100;
+
+/// Things that spend energy, so an actor's speed is measured against what it does.
+#[derive(Copy, Clone, Debug)]
+pub enum Action {
+    Move,
+    Attack,
+    CloseDoor,
+    PickUp,
+    Drop,
+    UseItem,
+    Equip,
+    Wait,
+}
+
+impl Action {
+    pub fn cost(self) -> i32 {
+        match self {
+            Action::Move => 100,
+            Action::Attack => 120,
+            Action::CloseDoor => 50,
+            Action::PickUp => 50,
+            Action::Drop => 50,
+            Action::UseItem => 100,
+            Action::Equip => 100,
+            Action::Wait => 100,
+        }
+    }
+}
+
+/// Energy spent stepping onto `tile`: a move scaled by the terrain's movement cost, so wading and
+/// clambering over rubble are slower than walking.
+pub fn move_cost(terrain: &TerrainTable, tile: TileType) -> i32 {
+    (Action::Move.cost() as f32 * terrain.get(tile).move_cost).round() as i32
+}
+
+/// Whose turn it is once someone has enough energy.
+pub enum Ready {
+    Player,
+    Monsters,
+}
+
+/// Hands out energy to everything alive on the current level, by its speed, until something can
+/// act. Whoever has the most energy goes first, and the player wins ties. Returns `None` when the
+/// player is dead or can't ever act.
+pub fn next_to_act(
+    actors: &mut Query<(&mut Energy, &Speed, &CombatStats, Option<&Player>), With<Position>>,
+) -> Option<Ready> {
+    let player_can_act = actors
+        .iter()
+        .any(|(_, speed, stats, player)| player.is_some() && stats.hp > 0 && speed.energy_per_tick > 0);
+    if !player_can_act {
+        return None;
+    }
+
+    loop {
+        let mut most_energy: Option<(i32, bool)> = None;
+        for (energy, _, stats, player) in actors.iter_mut() {
+            if stats.hp <= 0 || energy.amount < ACTION_THRESHOLD {
+                continue;
+            }
+            let is_player = player.is_some();
+            let goes_first = most_energy.map_or(true, |(most, _)| {
+                energy.amount > most || (energy.amount == most && is_player)
+            });
+            if goes_first {
+                most_energy = Some((energy.amount, is_player));
+            }
+        }
+        match most_energy {
+            Some((_, true)) => return Some(Ready::Player),
+            Some((_, false)) => return Some(Ready::Monsters),
+            None => {}
+        }
+
+        for (mut energy, speed, stats, _) in actors.iter_mut() {
+            if stats.hp > 0 {
+                energy.amount += speed.energy_per_tick;
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+/// Messages for the player, shown in the sidebar with the newest last.
+#[derive(Default)]
+pub struct MessageLog {
+    pub entries: Vec<String>,
+}
+
+impl MessageLog {
+    pub fn add(&mut self, message: impl Into<String>) {
+        self.entries.push(message.into());
+    }
+}
@@ -0,0 +1,560 @@
+use bevy::prelude::*;
+use bevy_tilemap::point::Point2;
+use bevy_tilemap::prelude::*;
+
+use crate::camera_system::cursor_tile;
+use crate::components::{
+    AreaOfEffect, CombatStats, Confused, Confusion, Consumable, DefenseBonus, Energy, Equippable, Equipped, InBackpack,
+    InflictsDamage, InventoryText, InventoryWindow, MainCamera, MeleePowerBonus, Name, Player, Position, ProvidesHealing,
+    Ranged, SufferDamage, Viewshed,
+};
+use crate::config::InputConfig;
+use crate::energy_system::Action;
+use crate::gamelog::MessageLog;
+use crate::map::Map;
+use crate::shadowcasting::RPAShadowcasting;
+use crate::terrain::TerrainTable;
+use crate::GameState;
+
+/// Keys that pick a line of the inventory, in the order the lines are listed.
+const ITEM_KEYS: [KeyCode; 26] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+];
+
+const INVENTORY_WIDTH: f32 = 300.0;
+
+/// Tint for the tiles a ranged item can be aimed at.
+const TARGET_HIGHLIGHT: Color = Color::rgb_linear(0.2, 0.2, 0.9);
+
+/// Sent when something uses an item it is carrying, on the point it aimed at for ranged items.
+pub struct WantsToUseItem {
+    pub user: Entity,
+    pub item: Entity,
+    pub target: Option<(i32, i32)>,
+}
+
+/// The ranged item waiting for the player to pick a target.
+#[derive(Default)]
+pub struct Targeting {
+    item: Option<Entity>,
+    range: i32,
+}
+
+/// Identical items the player carries, listed as one line of the inventory.
+struct ItemStack {
+    name: String,
+    items: Vec<Entity>,
+    equipped: bool,
+}
+
+/// Everything about an item the inventory can act on or describe.
+type ItemDetails = (
+    &'static Name,
+    Option<&'static Equippable>,
+    Option<&'static Equipped>,
+    Option<&'static ProvidesHealing>,
+    Option<&'static InflictsDamage>,
+    Option<&'static AreaOfEffect>,
+    Option<&'static Confusion>,
+    Option<&'static Ranged>,
+    Option<&'static Consumable>,
+    Option<&'static MeleePowerBonus>,
+    Option<&'static DefenseBonus>,
+);
+
+/// What an item does when used.
+type ItemEffects = (
+    &'static Name,
+    Option<&'static ProvidesHealing>,
+    Option<&'static InflictsDamage>,
+    Option<&'static AreaOfEffect>,
+    Option<&'static Confusion>,
+    Option<&'static Consumable>,
+);
+
+/// What the inventory screen is showing.
+#[derive(Default)]
+pub struct InventoryMenu {
+    stacks: Vec<ItemStack>,
+    /// The line picked to act on, if any.
+    selected: Option<usize>,
+    /// Opened with the drop key, so picking a line drops one item of it straight away.
+    pub dropping: bool,
+}
+
+pub fn open_inventory(
+    mut commands: Commands,
+    mut menu: ResMut<InventoryMenu>,
+    input_config: Res<InputConfig>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    player_query: Query<Entity, With<Player>>,
+    backpack_query: Query<(Entity, &Name, &InBackpack, Option<&Equipped>)>,
+) {
+    let player = player_query.single().expect("There should only be one player");
+    let mut stacks: Vec<ItemStack> = Vec::new();
+    for (item, name, backpack, equipped) in backpack_query.iter() {
+        if backpack.owner != player {
+            continue;
+        }
+        let equipped = equipped.is_some();
+        match stacks
+            .iter_mut()
+            .find(|stack| stack.name == name.name && stack.equipped == equipped)
+        {
+            Some(stack) => stack.items.push(item),
+            None => stacks.push(ItemStack {
+                name: name.name.clone(),
+                items: vec![item],
+                equipped,
+            }),
+        }
+    }
+    // Keeps each item on the same letter from one look to the next
+    stacks.sort_by(|a, b| a.name.cmp(&b.name).then(b.equipped.cmp(&a.equipped)));
+    stacks.truncate(ITEM_KEYS.len());
+    *menu = InventoryMenu {
+        stacks,
+        selected: None,
+        dropping: menu.dropping,
+    };
+
+    let font = asset_server.load("fonts/CascadiaCode.ttf");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(INVENTORY_WIDTH), Val::Auto),
+                border: Rect::all(Val::Px(2.0)),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(20.0),
+                    left: Val::Px(20.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material: materials.add(Color::WHITE.into()),
+            ..Default::default()
+        })
+        .insert(InventoryWindow)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.0), Val::Auto),
+                        padding: Rect::all(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::BLACK.into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                menu_text(&menu, &input_config),
+                                TextStyle {
+                                    font,
+                                    font_size: 12.0,
+                                    color: Color::WHITE,
+                                },
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        })
+                        .insert(InventoryText);
+                });
+        });
+}
+
+/// Picks a line with its letter, then acts on one item of it, or drops it straight away when
+/// opened with the drop key. Using, dropping or equipping spends the player's turn; looking and
+/// backing out don't.
+#[allow(clippy::too_many_arguments)]
+pub fn inventory_input(
+    mut commands: Commands,
+    mut game_state: ResMut<State<GameState>>,
+    mut menu: ResMut<InventoryMenu>,
+    keyboard_input: Res<Input<KeyCode>>,
+    input_config: Res<InputConfig>,
+    mut log: ResMut<MessageLog>,
+    mut targeting: ResMut<Targeting>,
+    mut use_events: EventWriter<WantsToUseItem>,
+    mut player_query: Query<(Entity, &Position, &mut Energy), With<Player>>,
+    item_query: Query<ItemDetails>,
+    equipped_query: Query<(Entity, &Name, &Equipped)>,
+    mut text_query: Query<&mut Text, With<InventoryText>>,
+) {
+    let (player, position, mut energy) = player_query.single_mut().expect("There should only be one player");
+
+    for key in keyboard_input.get_just_pressed() {
+        let selected = match menu.selected {
+            Some(selected) => selected,
+            None => {
+                if key == &input_config.cancel {
+                    game_state.set(GameState::AwaitingInput).unwrap();
+                    return;
+                }
+                if let Some(index) = ITEM_KEYS.iter().position(|item_key| item_key == key) {
+                    if index < menu.stacks.len() && menu.dropping {
+                        let item = menu.stacks[index].items[0];
+                        let (name, _, equipped, ..) = item_query.get(item).expect("Inventory lists a missing item");
+                        drop_item(&mut commands, &mut log, item, name, equipped.is_some(), position);
+                        energy.amount -= Action::Drop.cost();
+                        game_state.set(GameState::PlayerTurn).unwrap();
+                        return;
+                    } else if index < menu.stacks.len() {
+                        menu.selected = Some(index);
+                    }
+                }
+                continue;
+            }
+        };
+
+        let item = menu.stacks[selected].items[0];
+        let (name, _, equipped, healing, damage, area, confusion, ranged, consumable, power, defense) =
+            item_query.get(item).expect("Inventory lists a missing item");
+        let action = if key == &input_config.cancel {
+            menu.selected = None;
+            None
+        } else if key == &input_config.use_item {
+            if let Some(ranged) = ranged {
+                // The turn is spent once a target is picked
+                *targeting = Targeting {
+                    item: Some(item),
+                    range: ranged.range,
+                };
+                game_state.set(GameState::ShowTargeting).unwrap();
+                return;
+            } else if healing.is_some() || damage.is_some() || confusion.is_some() {
+                use_events.send(WantsToUseItem {
+                    user: player,
+                    item,
+                    target: None,
+                });
+                Some(Action::UseItem)
+            } else {
+                log.add(format!("You can't use the {}.", name.name));
+                None
+            }
+        } else if key == &input_config.drop {
+            drop_item(&mut commands, &mut log, item, name, equipped.is_some(), position);
+            Some(Action::Drop)
+        } else if key == &input_config.equip {
+            if equip(&mut commands, &mut log, &item_query, &equipped_query, player, item) {
+                Some(Action::Equip)
+            } else {
+                None
+            }
+        } else if key == &input_config.inspect {
+            let mut details = Vec::new();
+            if let Some(healing) = healing {
+                details.push(format!("heals {} hp", healing.amount));
+            }
+            if let Some(damage) = damage {
+                details.push(format!("deals {} damage", damage.damage));
+            }
+            if let Some(confusion) = confusion {
+                details.push(format!("confuses for {} turns", confusion.turns));
+            }
+            if let Some(area) = area {
+                details.push(format!("hits everything within {} tiles", area.radius));
+            }
+            if let Some(ranged) = ranged {
+                details.push(format!("aimed up to {} tiles away", ranged.range));
+            }
+            if let Some(power) = power {
+                details.push(format!("{:+} power", power.power));
+            }
+            if let Some(defense) = defense {
+                details.push(format!("{:+} defense", defense.defense));
+            }
+            if consumable.is_some() {
+                details.push("used up when used".to_string());
+            }
+            if equipped.is_some() {
+                details.push("equipped".to_string());
+            }
+            if details.is_empty() {
+                log.add(format!("{}: nothing special.", name.name));
+            } else {
+                log.add(format!("{}: {}.", name.name, details.join(", ")));
+            }
+            None
+        } else {
+            continue;
+        };
+
+        if let Some(action) = action {
+            energy.amount -= action.cost();
+            game_state.set(GameState::PlayerTurn).unwrap();
+            return;
+        }
+    }
+
+    if menu.is_changed() {
+        for mut text in text_query.iter_mut() {
+            text.sections[0].value = menu_text(&menu, &input_config);
+        }
+    }
+}
+
+pub fn close_inventory(mut commands: Commands, window_query: Query<Entity, With<InventoryWindow>>) {
+    for window in window_query.iter() {
+        commands.entity(window).despawn_recursive();
+    }
+}
+
+/// Shows where the item being aimed can reach.
+pub fn start_targeting(
+    targeting: Res<Targeting>,
+    input_config: Res<InputConfig>,
+    mut log: ResMut<MessageLog>,
+    mut tilemap_query: Query<&mut Tilemap>,
+    player_query: Query<(&Position, &Viewshed), With<Player>>,
+) {
+    let mut tilemap = tilemap_query
+        .single_mut()
+        .expect("There should only be one map");
+    let (position, viewshed) = player_query.single().expect("There should only be one player");
+    for point in viewshed.visible_tiles.iter() {
+        if in_range(position, (point.x, point.y), targeting.range) {
+            let mut tile = tilemap.get_tile_mut(*point, 0).expect("Nonexistent Tile");
+            tile.color = TARGET_HIGHLIGHT;
+        }
+    }
+    log.add(format!("Click on a target, or press {:?} to cancel.", input_config.cancel));
+}
+
+/// Uses the item being aimed on the tile clicked, if the player can see it and it's in range.
+#[allow(clippy::too_many_arguments)]
+pub fn targeting_input(
+    mut game_state: ResMut<State<GameState>>,
+    targeting: Res<Targeting>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    input_config: Res<InputConfig>,
+    windows: Res<Windows>,
+    map_data: Res<Map>,
+    mut log: ResMut<MessageLog>,
+    mut use_events: EventWriter<WantsToUseItem>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+    mut player_query: Query<(Entity, &Position, &Viewshed, &mut Energy), With<Player>>,
+) {
+    if keyboard_input.just_pressed(input_config.cancel) {
+        game_state.set(GameState::AwaitingInput).unwrap();
+        return;
+    }
+    if !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let camera = camera_query.single().expect("There should only be one main camera");
+    let target = match cursor_tile(&windows, camera, &map_data) {
+        Some(target) => target,
+        None => return,
+    };
+    let (player, position, viewshed, mut energy) = player_query.single_mut().expect("There should only be one player");
+    let visible = viewshed.visible_tiles.contains(&Point2::new(target.0, target.1));
+    if !visible || !in_range(position, target, targeting.range) {
+        log.add("That's out of range.");
+        return;
+    }
+
+    use_events.send(WantsToUseItem {
+        user: player,
+        item: targeting.item.expect("Targeting without an item"),
+        target: Some(target),
+    });
+    energy.amount -= Action::UseItem.cost();
+    game_state.set(GameState::PlayerTurn).unwrap();
+}
+
+/// Works out who an item affects, then applies each of its effects to each of them, so effects
+/// combine however items are put together. Items without a target affect their user.
+pub fn item_use(
+    mut commands: Commands,
+    mut log: ResMut<MessageLog>,
+    mut use_events: EventReader<WantsToUseItem>,
+    map_data: Res<Map>,
+    terrain: Res<TerrainTable>,
+    item_query: Query<ItemEffects>,
+    mut target_query: Query<(&Name, &mut CombatStats, &mut SufferDamage)>,
+) {
+    for event in use_events.iter() {
+        let (name, healing, damage, area, confusion, consumable) = match item_query.get(event.item) {
+            Ok(item) => item,
+            Err(_) => continue,
+        };
+        log.add(format!("You use the {}.", name.name));
+
+        let targets: Vec<Entity> = match event.target {
+            None => vec![event.user],
+            Some(point) => affected_tiles(&map_data, &terrain, point, area)
+                .into_iter()
+                .flat_map(|(x, y)| map_data.tile_content[map_data.xy_idx(x, y)].iter().copied())
+                .collect(),
+        };
+        for target in targets {
+            let (target_name, mut stats, mut suffer_damage) = match target_query.get_mut(target) {
+                Ok(target) => target,
+                Err(_) => continue,
+            };
+            if stats.hp <= 0 {
+                continue;
+            }
+            if let Some(healing) = healing {
+                let healed = healing.amount.min(stats.max_hp - stats.hp);
+                stats.hp += healed;
+                log.add(format!("{} heals {} hp.", target_name.name, healed));
+            }
+            if let Some(damage) = damage {
+                suffer_damage.amount.push(damage.damage);
+                log.add(format!("{} takes {} damage.", target_name.name, damage.damage));
+            }
+            if let Some(confusion) = confusion {
+                commands.entity(target).insert(Confused { turns: confusion.turns });
+                log.add(format!("{} is confused.", target_name.name));
+            }
+        }
+
+        if consumable.is_some() {
+            commands.entity(event.item).despawn();
+        }
+    }
+}
+
+/// The tiles an item aimed at `target` hits: just that one, or for area effects every tile in
+/// the radius that isn't hidden from `target` by a wall.
+fn affected_tiles(map_data: &Map, terrain: &TerrainTable, target: (i32, i32), area: Option<&AreaOfEffect>) -> Vec<(i32, i32)> {
+    let radius = match area {
+        Some(area) => area.radius,
+        None => return vec![target],
+    };
+    RPAShadowcasting::new(radius, |x, y| match map_data.get(target.0 + x, target.1 + y) {
+        Some(tile) => terrain.get(tile).opacity >= 1.0,
+        None => true,
+    })
+    .filter(|&(_, _, visible)| visible)
+    .map(|(x, y, _)| (target.0 + x, target.1 + y))
+    .filter(|&(x, y)| map_data.in_bounds(x, y))
+    .collect()
+}
+
+fn in_range(position: &Position, (x, y): (i32, i32), range: i32) -> bool {
+    let (dx, dy) = (x - position.x, y - position.y);
+    dx * dx + dy * dy <= range * range
+}
+
+/// Puts a carried item down at `position`, taking it off first if it was equipped.
+fn drop_item(
+    commands: &mut Commands,
+    log: &mut MessageLog,
+    item: Entity,
+    name: &Name,
+    equipped: bool,
+    position: &Position,
+) {
+    commands
+        .entity(item)
+        .remove::<InBackpack>()
+        .remove::<Equipped>()
+        .insert(*position);
+    if equipped {
+        log.add(format!("You take off the {} and drop it.", name.name));
+    } else {
+        log.add(format!("You drop the {}.", name.name));
+    }
+}
+
+/// Equips `item`, swapping out whatever was in its slot, or takes it off if it was already
+/// equipped. Returns whether anything changed.
+fn equip(
+    commands: &mut Commands,
+    log: &mut MessageLog,
+    item_query: &Query<ItemDetails>,
+    equipped_query: &Query<(Entity, &Name, &Equipped)>,
+    player: Entity,
+    item: Entity,
+) -> bool {
+    let (name, equippable, equipped, ..) = item_query.get(item).expect("Can't equip a missing item");
+    if equipped.is_some() {
+        commands.entity(item).remove::<Equipped>();
+        log.add(format!("You take off the {}.", name.name));
+        return true;
+    }
+    let slot = match equippable {
+        Some(equippable) => equippable.slot,
+        None => {
+            log.add(format!("You can't equip the {}.", name.name));
+            return false;
+        }
+    };
+
+    for (other, other_name, other_equipped) in equipped_query.iter() {
+        if other_equipped.owner == player && other_equipped.slot == slot {
+            commands.entity(other).remove::<Equipped>();
+            log.add(format!("You take off the {}.", other_name.name));
+        }
+    }
+    commands.entity(item).insert(Equipped { owner: player, slot });
+    log.add(format!("You equip the {}.", name.name));
+    true
+}
+
+fn menu_text(menu: &InventoryMenu, input_config: &InputConfig) -> String {
+    match menu.selected {
+        None => {
+            let mut text = String::from(if menu.dropping { "Drop which item?\n\n" } else { "Inventory\n\n" });
+            if menu.stacks.is_empty() {
+                text.push_str("You aren't carrying anything.\n");
+            }
+            for (i, stack) in menu.stacks.iter().enumerate() {
+                let letter = (b'a' + i as u8) as char;
+                text.push_str(&format!("{}) {}", letter, stack.name));
+                if stack.items.len() > 1 {
+                    text.push_str(&format!(" x{}", stack.items.len()));
+                }
+                if stack.equipped {
+                    text.push_str(" (equipped)");
+                }
+                text.push('\n');
+            }
+            text.push_str(&format!("\n{:?}: Close", input_config.cancel));
+            text
+        }
+        Some(selected) => format!(
+            "{}\n\n{:?}: Use\n{:?}: Drop\n{:?}: Equip\n{:?}: Inspect\n{:?}: Back",
+            menu.stacks[selected].name,
+            input_config.use_item,
+            input_config.drop,
+            input_config.equip,
+            input_config.inspect,
+            input_config.cancel,
+        ),
+    }
+}
@@ -3,22 +3,44 @@ use bevy::{
     prelude::*,
 };
 use bevy_tilemap::prelude::*;
-use map::Map;
+use combat_system::WantsToMelee;
+use components::MainCamera;
+use dijkstra::PlayerDistanceMap;
+use editor_system::EditorState;
+use gamelog::MessageLog;
+use inventory_system::{InventoryMenu, Targeting, WantsToUseItem};
+use playback_system::MapgenPlayback;
+use map::{Depth, LevelCache, Map};
 
+mod camera_system;
+mod combat_system;
 mod character;
 mod components;
 mod config;
+mod dijkstra;
+mod editor_system;
+mod energy_system;
+mod gamelog;
+mod inventory_system;
 mod map;
+mod map_builders;
 mod map_system;
+mod monster_ai_system;
+mod playback_system;
 mod player;
 mod rect;
+mod rng;
 mod shadowcasting;
+mod spawner;
 mod state_manager_system;
+mod terrain;
 mod ui;
 mod visibility_system;
 
-const ARENA_WIDTH: i32 = 80;
-const ARENA_HEIGHT: i32 = 50;
+// Size of the visible part of the map, in tiles
+const VIEW_WIDTH: i32 = 80;
+const VIEW_HEIGHT: i32 = 50;
+const CHUNK_SIZE: i32 = 32;
 const UI_WIDTH: f32 = 200.0;
 const FONT_WIDTH: f32 = 8.0;
 const FONT_HEIGHT: f32 = 8.0;
@@ -30,7 +52,23 @@ pub enum GameState {
     AwaitingInput,
     PreRun,
     PlayerTurn,
+    /// Every monster with enough energy acts once.
+    MonsterTurn,
+    /// Handing out energy until someone can act.
+    Ticking,
+    /// The player is dead, so nothing acts any more.
+    GameOver,
     Running,
+    NextLevel,
+    PreviousLevel,
+    /// Turns are frozen while the level is edited with the mouse.
+    Editor,
+    /// Playing back how the new level was built.
+    ShowingMapgen,
+    /// Looking through what the player carries.
+    ShowInventory,
+    /// Picking where to aim a ranged item.
+    ShowTargeting,
 }
 
 pub fn setup(
@@ -48,8 +86,7 @@ pub fn setup(
     let texture_atlas_handle = texture_atlases.add(texture_atlas);
 
     let tilemap = Tilemap::builder()
-        .dimensions(1, 1)
-        .chunk_dimensions(ARENA_WIDTH as u32, ARENA_HEIGHT as u32, 1)
+        .chunk_dimensions(CHUNK_SIZE as u32, CHUNK_SIZE as u32, 1)
         .texture_dimensions(FONT_WIDTH as u32, FONT_HEIGHT as u32)
         .texture_atlas(texture_atlas_handle.clone())
         // Map layer
@@ -89,7 +126,7 @@ pub fn setup(
     // Spawn Camera
     let mut camera = OrthographicCameraBundle::new_2d();
     camera.transform.translation.x = UI_WIDTH / 2.0;
-    commands.spawn_bundle(camera);
+    commands.spawn_bundle(camera).insert(MainCamera);
     commands
         .spawn()
         .insert_bundle(tilemap_components)
@@ -100,8 +137,8 @@ fn main() {
     App::build()
         .insert_resource(WindowDescriptor {
             title: "Roguelike Tutorial 2021".to_string(),
-            width: ARENA_WIDTH as f32 * FONT_WIDTH + UI_WIDTH,
-            height: ARENA_HEIGHT as f32 * FONT_HEIGHT,
+            width: VIEW_WIDTH as f32 * FONT_WIDTH + UI_WIDTH,
+            height: VIEW_HEIGHT as f32 * FONT_HEIGHT,
             resizable: false,
             vsync: false,
             ..Default::default()
@@ -113,9 +150,23 @@ fn main() {
         .add_state(GameState::PreRun)
         .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
         .insert_resource(Map::default())
+        .insert_resource(Depth(1))
+        .insert_resource(LevelCache::default())
+        .insert_resource(PlayerDistanceMap::default())
+        .insert_resource(EditorState::default())
+        .insert_resource(MapgenPlayback::default())
+        .insert_resource(MessageLog::default())
+        .insert_resource(InventoryMenu::default())
+        .insert_resource(Targeting::default())
+        .add_event::<WantsToMelee>()
+        .add_event::<WantsToUseItem>()
         .add_startup_system(setup.system())
         .add_startup_system(config::open_config.system())
         .add_system(state_manager_system::state_manager.system())
+        .add_system(ui::update_depth.system())
+        .add_system(ui::update_log.system())
+        .add_system(camera_system::follow_player.system())
+        .add_system(map_system::dump_map.system())
         .add_system_set(SystemSet::on_enter(GameState::PreRun).with_system(map::build_map.system()).with_system(ui::setup_ui.system()))
         .add_system_set(
             SystemSet::on_update(GameState::AwaitingInput)
@@ -130,9 +181,46 @@ fn main() {
         // )
         .add_system_set(
             SystemSet::on_enter(GameState::PlayerTurn)
-                .with_system(visibility_system::visibility.system().label("visibility"))
-                .with_system(map_system::map_indexing.system().after("visibility"))
+                .with_system(inventory_system::item_use.system().label("item_use"))
+                .with_system(combat_system::melee_combat.system().label("melee"))
+                .with_system(combat_system::damage.system().label("damage").after("melee").after("item_use"))
+                .with_system(combat_system::delete_the_dead.system().label("dead").after("damage"))
+                .with_system(visibility_system::visibility.system().label("visibility").after("dead"))
+                .with_system(map_system::map_indexing.system().label("indexing").after("visibility"))
+                .with_system(map_system::player_distance_map.system().after("indexing"))
+                .with_system(map_system::announce_rooms.system())
                 .with_system(map_system::draw_map.system().after("visibility")),
         )
+        .add_system_set(
+            SystemSet::on_enter(GameState::MonsterTurn)
+                .with_system(monster_ai_system::monster_ai.system().label("monster_ai"))
+                .with_system(combat_system::melee_combat.system().label("monster_melee").after("monster_ai"))
+                .with_system(combat_system::damage.system().label("monster_damage").after("monster_melee"))
+                .with_system(combat_system::delete_the_dead.system().label("monster_dead").after("monster_damage"))
+                .with_system(visibility_system::visibility.system().label("monster_visibility").after("monster_dead"))
+                .with_system(map_system::map_indexing.system().after("monster_visibility"))
+                .with_system(map_system::draw_map.system().after("monster_visibility")),
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::Editor)
+                .with_system(editor_system::enter_editor.system().label("enter_editor"))
+                .with_system(map_system::draw_map.system().after("enter_editor")),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Editor)
+                .with_system(editor_system::editor_input.system())
+                .with_system(editor_system::paint.system()),
+        )
+        .add_system_set(SystemSet::on_exit(GameState::Editor).with_system(editor_system::exit_editor.system()))
+        .add_system_set(SystemSet::on_enter(GameState::ShowInventory).with_system(inventory_system::open_inventory.system()))
+        .add_system_set(SystemSet::on_update(GameState::ShowInventory).with_system(inventory_system::inventory_input.system()))
+        .add_system_set(SystemSet::on_exit(GameState::ShowInventory).with_system(inventory_system::close_inventory.system()))
+        .add_system_set(SystemSet::on_enter(GameState::ShowTargeting).with_system(inventory_system::start_targeting.system()))
+        .add_system_set(SystemSet::on_update(GameState::ShowTargeting).with_system(inventory_system::targeting_input.system()))
+        // Puts back the map colours the target highlight covered
+        .add_system_set(SystemSet::on_exit(GameState::ShowTargeting).with_system(map_system::draw_map.system()))
+        .add_system_set(SystemSet::on_update(GameState::ShowingMapgen).with_system(playback_system::play_back.system()))
+        .add_system_set(SystemSet::on_enter(GameState::NextLevel).with_system(map::change_level.system()))
+        .add_system_set(SystemSet::on_enter(GameState::PreviousLevel).with_system(map::change_level.system()))
         .run();
 }
@@ -1,181 +1,351 @@
 use bevy::prelude::*;
 use bevy_tilemap::prelude::*;
-use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::components::{EnemyBundle, Player, PlayerBundle, Position, Render, Viewshed};
-use crate::{rect, GameState, ARENA_HEIGHT, ARENA_WIDTH};
+use crate::character::move_sprite;
+use crate::components::{Player, Position, Render, Viewshed};
+use crate::config::MapGenConfig;
+use crate::map_builders::{self, MapBuild, PrefabLibrary, Snapshots};
+use crate::playback_system::MapgenPlayback;
+use crate::rng::MapSeed;
+use crate::spawner;
+use crate::terrain::TerrainTable;
+use crate::{rect, GameState, CHUNK_SIZE};
+use crate::dijkstra::OpenNode;
 use std::cmp::{max, min};
+use std::collections::{BinaryHeap, HashMap};
 
+pub static ORTHOGONAL_DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+static DIAGONAL_DIRECTIONS: [(i32, i32); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+const DIAGONAL_COST: f32 = std::f32::consts::SQRT_2;
+
+/// How far down the dungeon the player is, starting at 1.
+pub struct Depth(pub i32);
+
+#[allow(clippy::too_many_arguments)]
 pub fn build_map(
     mut commands: Commands,
     mut game_state: ResMut<State<GameState>>,
     mut map_data: ResMut<Map>,
+    depth: Res<Depth>,
+    seed: Res<MapSeed>,
+    mapgen_config: Res<MapGenConfig>,
+    prefabs: Res<PrefabLibrary>,
+    mut playback: ResMut<MapgenPlayback>,
     mut query: Query<&mut Tilemap>,
 ) {
     for mut map in query.iter_mut() {
         info!("Loading Map");
 
-        map.insert_chunk((0, 0)).unwrap();
-
         let mut tiles = Vec::new();
-        let mut rooms = Vec::new();
-
-        // Fill with blanks
-        for y in 0..ARENA_HEIGHT {
-            for x in 0..ARENA_WIDTH {
-                let y = y - ARENA_HEIGHT / 2;
-                let x = x - ARENA_WIDTH / 2;
-
-                let tile = Tile {
-                    point: (x, y),
-                    sprite_index: ' ' as usize,
-                    sprite_order: 0,
-                    tint: Color::GRAY,
-                };
-
-                tiles.push(tile);
-            }
+        let (player_start, frames) = generate_level(
+            &mut commands,
+            &mut tiles,
+            &mut map_data,
+            &mapgen_config,
+            &prefabs,
+            *seed,
+            depth.0,
+        );
+        playback.frames = frames;
+
+        // Spawn Player
+        spawner::player(&mut commands, &mut tiles, player_start);
+
+        // Every level has the configured size, so the chunks only need creating once
+        let chunks = chunk_points(&map_data);
+        for chunk in chunks.iter() {
+            map.insert_chunk(*chunk).unwrap();
         }
 
-        const MAX_ROOMS: i32 = 30;
-        const MIN_SIZE: i32 = 6;
-        const MAX_SIZE: i32 = 10;
+        map.insert_tiles(tiles).unwrap();
 
-        let mut rng = thread_rng();
+        for chunk in chunks.iter() {
+            map.spawn_chunk(*chunk).unwrap();
+        }
 
-        // Spawn Rooms
-        for _ in 0..MAX_ROOMS {
-            let w = rng.gen_range(MIN_SIZE..=MAX_SIZE);
-            let h = rng.gen_range(MIN_SIZE..=MAX_SIZE);
-            let x = rng.gen_range((-map_data.width / 2)..(map_data.width / 2 - w - 1));
-            let y = rng.gen_range((-map_data.height / 2)..(map_data.height / 2 - h - 1));
-            let new_room = rect::Rect::new(x, y, w, h);
-            let mut ok = true;
-            for other_room in rooms.iter() {
-                if new_room.intersect(other_room) {
-                    ok = false
-                }
-            }
-            if ok {
-                map_data.apply_room(&new_room);
-
-                if !rooms.is_empty() {
-                    let (new_x, new_y) = new_room.center();
-                    let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-                    if rng.gen() {
-                        map_data.apply_horizontal_tunnel(prev_x, new_x, prev_y);
-                        map_data.apply_vertical_tunnel(prev_y, new_y, new_x);
-                    } else {
-                        map_data.apply_vertical_tunnel(prev_y, new_y, prev_x);
-                        map_data.apply_horizontal_tunnel(prev_x, new_x, new_y);
-                    }
-                }
+        game_state.set(first_state(&playback)).unwrap();
+    }
+}
+
+/// A level the player has left, kept so it can be restored exactly as it was.
+pub struct StoredLevel {
+    /// The `Map` serialized as RON.
+    map: String,
+    /// Entities that were on the level. They are kept alive without a `Position` while
+    /// stored, which takes them out of every system that acts on the current level.
+    entities: Vec<(Entity, Position)>,
+}
+
+#[derive(Default)]
+pub struct LevelCache {
+    pub levels: HashMap<i32, StoredLevel>,
+}
+
+/// Moves the player a level down on `GameState::NextLevel` or a level up on
+/// `GameState::PreviousLevel`, storing the level being left and restoring the one being
+/// entered if it was visited before.
+#[allow(clippy::too_many_arguments)]
+pub fn change_level(
+    mut commands: Commands,
+    mut game_state: ResMut<State<GameState>>,
+    mut map_data: ResMut<Map>,
+    mut depth: ResMut<Depth>,
+    mut level_cache: ResMut<LevelCache>,
+    seed: Res<MapSeed>,
+    mapgen_config: Res<MapGenConfig>,
+    prefabs: Res<PrefabLibrary>,
+    mut playback: ResMut<MapgenPlayback>,
+    mut tilemap_query: Query<&mut Tilemap>,
+    level_query: Query<(Entity, &Position, &Render), Without<Player>>,
+    mut player_query: Query<(&mut Position, &Render, &mut Viewshed), With<Player>>,
+) {
+    let mut tilemap = tilemap_query
+        .single_mut()
+        .expect("There should only be one map");
+
+    let mut entities = Vec::new();
+    for (entity, position, render) in level_query.iter() {
+        tilemap
+            .clear_tile((position.x, position.y), render.sprite_order)
+            .unwrap();
+        commands.entity(entity).remove::<Position>();
+        entities.push((entity, *position));
+    }
+    level_cache.levels.insert(
+        depth.0,
+        StoredLevel {
+            map: ron::to_string(&*map_data).expect("Unable to serialize map"),
+            entities,
+        },
+    );
 
-                rooms.push(new_room);
+    let descending = *game_state.current() == GameState::NextLevel;
+    depth.0 += if descending { 1 } else { -1 };
+    info!("Moving to depth {}", depth.0);
+
+    let mut tiles = Vec::new();
+    let player_start = match level_cache.levels.remove(&depth.0) {
+        Some(stored) => {
+            let mut restored: Map = ron::from_str(&stored.map).expect("Unable to deserialize map");
+            restored.tile_content = vec![Vec::new(); restored.tiles.len()];
+            *map_data = restored;
+            for (entity, position) in stored.entities {
+                commands.entity(entity).insert(position);
             }
-        }
+            push_blank_tiles(&mut tiles, &map_data);
 
-        // Spawn Enemies
-        for room in rooms.iter().skip(1) {
-            let (x,y) = room.center();
-            let enemy_tile = Tile {
-                point: (x, y),
-                sprite_order: 2,
-                sprite_index: 'g' as usize,
-                tint: Color::RED,
+            // Arrive on the stairs leading back where the player came from
+            let arrival = if descending {
+                TileType::UpStairs
+            } else {
+                TileType::DownStairs
             };
-            tiles.push(enemy_tile);
-            commands.spawn_bundle(EnemyBundle {
-                position: Position {
-                    x,
-                    y,
-                },
-                render: Render {
-                    sprite_index: 'g' as usize,
-                    sprite_order: 2,
-                    tint: Color::RED,
-                },
-                viewshed: Viewshed {
-                    visible_tiles: Vec::new(),
-                    range: 8,
-                    dirty: true
-                }
-            });
+            let idx = map_data
+                .tiles
+                .iter()
+                .position(|tile| *tile == arrival)
+                .expect("Stored level has no stairs");
+            map_data.idx_xy(idx)
         }
+        None => {
+            let (player_start, frames) = generate_level(
+                &mut commands,
+                &mut tiles,
+                &mut map_data,
+                &mapgen_config,
+                &prefabs,
+                *seed,
+                depth.0,
+            );
+            playback.frames = frames;
+            player_start
+        }
+    };
 
-        // Spawn Player
-        let player_index = '@' as usize;
-        let (player_x, player_y) = rooms[0].center();
-
-        let player_tile = Tile {
-            point: (player_x, player_y),
-            sprite_order: 2,
-            sprite_index: player_index,
-            tint: Color::GREEN,
-        };
-        tiles.push(player_tile);
-
-        commands.spawn().insert_bundle(PlayerBundle {
-            player: Player,
-            position: Position {
-                x: player_x,
-                y: player_y,
-            },
-            render: Render {
-                sprite_index: player_index,
-                sprite_order: 2,
-                tint: Color::GREEN,
-            },
-            viewshed: Viewshed {
-                visible_tiles: Vec::new(),
-                range: 8,
-                dirty: true,
-            },
-        });
+    let (mut position, render, mut viewshed) = player_query
+        .single_mut()
+        .expect("There should only be one player");
+    let previous_position = *position;
+    *position = Position {
+        x: player_start.0,
+        y: player_start.1,
+    };
+    move_sprite(&mut tilemap, previous_position, *position, render);
+    viewshed.dirty = true;
 
-        map_data.rooms = rooms;
+    // Overwrites every tile of the map layer with blanks, hiding the old level
+    tilemap.insert_tiles(tiles).unwrap();
 
-        map.insert_tiles(tiles).unwrap();
+    game_state.set(first_state(&playback)).unwrap();
+}
+
+/// Where to go once a level is ready: watching it being built if that was recorded, otherwise
+/// straight into play.
+fn first_state(playback: &MapgenPlayback) -> GameState {
+    if playback.frames.is_empty() {
+        GameState::PlayerTurn
+    } else {
+        GameState::ShowingMapgen
+    }
+}
+
+/// Builds the level at `depth` into `map_data`, spawns everything on it except the player and
+/// queues its tiles, including a blank map layer. Returns where the player should start and the
+/// snapshots of it being built, if they were recorded.
+fn generate_level(
+    commands: &mut Commands,
+    tiles: &mut Vec<Tile>,
+    map_data: &mut Map,
+    mapgen_config: &MapGenConfig,
+    prefabs: &PrefabLibrary,
+    seed: MapSeed,
+    depth: i32,
+) -> ((i32, i32), Vec<Vec<TileType>>) {
+    let mut snapshots = Snapshots::new(mapgen_config.show_generation);
+    let MapBuild {
+        map: built_map,
+        player_start,
+        spawn_points,
+        named_spawns,
+    } = map_builders::build_level(mapgen_config, &prefabs.0, depth, &mut seed.level_rng(depth), &mut snapshots);
+    *map_data = built_map;
+
+    push_blank_tiles(tiles, map_data);
+
+    // Spawn Enemies
+    for point in spawn_points {
+        spawner::goblin(commands, tiles, point);
+    }
+    for (point, name) in named_spawns.iter() {
+        spawner::named(commands, tiles, name, *point);
+    }
+
+    (player_start, snapshots.frames)
+}
+
+/// The tilemap chunks covering `map_data`. Chunk `(0, 0)` is centred on the origin, like the map.
+fn chunk_points(map_data: &Map) -> Vec<(i32, i32)> {
+    let to_chunk = |point: i32| (point + CHUNK_SIZE / 2).div_euclid(CHUNK_SIZE);
+    let (min_x, max_x) = (to_chunk(-map_data.width / 2), to_chunk(map_data.width - map_data.width / 2 - 1));
+    let (min_y, max_y) = (to_chunk(-map_data.height / 2), to_chunk(map_data.height - map_data.height / 2 - 1));
+
+    let mut chunks = Vec::new();
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            chunks.push((x, y));
+        }
+    }
+    chunks
+}
+
+/// Queues a blank tile on the map layer for every point of `map_data`.
+fn push_blank_tiles(tiles: &mut Vec<Tile>, map_data: &Map) {
+    for y in 0..map_data.height {
+        for x in 0..map_data.width {
+            let y = y - map_data.height / 2;
+            let x = x - map_data.width / 2;
 
-        map.spawn_chunk((0, 0)).unwrap();
+            let tile = Tile {
+                point: (x, y),
+                sprite_index: ' ' as usize,
+                sprite_order: 0,
+                tint: Color::GRAY,
+            };
 
-        game_state.set(GameState::PlayerTurn).unwrap();
+            tiles.push(tile);
+        }
     }
 }
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum TileType {
     Floor,
     Wall,
+    DownStairs,
+    UpStairs,
+    ClosedDoor,
+    OpenDoor,
+    ShallowWater,
+    DeepWater,
+    Lava,
+    TallGrass,
+    Rubble,
+    Chasm,
+    Bookshelf,
+    Bed,
+    Altar,
+    Pillar,
 }
 
+/// What a room is for, which decides how it is furnished and what lives in it.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum RoomTheme {
+    Plain,
+    Library,
+    Barracks,
+    Treasury,
+    Shrine,
+    Flooded,
+}
+
+impl RoomTheme {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RoomTheme::Plain => "room",
+            RoomTheme::Library => "library",
+            RoomTheme::Barracks => "barracks",
+            RoomTheme::Treasury => "treasury",
+            RoomTheme::Shrine => "shrine",
+            RoomTheme::Flooded => "flooded room",
+        }
+    }
+}
+
+/// Metadata for the room at the same index in `Map::rooms`.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub theme: RoomTheme,
+    /// Whether the player has been inside yet.
+    pub entered: bool,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Map {
     pub tiles: Vec<TileType>,
     pub rooms: Vec<rect::Rect>,
+    pub room_info: Vec<RoomInfo>,
     pub width: i32,
     pub height: i32,
     pub revealed_tiles: Vec<bool>,
     pub visible_tiles: Vec<bool>,
     pub blocked: Vec<bool>,
+    #[serde(skip)]
     pub tile_content: Vec<Vec<Entity>>,
 }
 
 impl Default for Map {
     fn default() -> Self {
+        Self::new(0, 0)
+    }
+}
+
+impl Map {
+    pub fn new(width: i32, height: i32) -> Self {
+        let size = (width * height) as usize;
         Self {
-            tiles: vec![TileType::Wall; (ARENA_WIDTH * ARENA_HEIGHT) as usize],
+            tiles: vec![TileType::Wall; size],
             rooms: Vec::new(),
-            width: ARENA_WIDTH,
-            height: ARENA_HEIGHT,
-            revealed_tiles: vec![false; (ARENA_WIDTH * ARENA_HEIGHT) as usize],
-            visible_tiles: vec![false; (ARENA_WIDTH * ARENA_HEIGHT) as usize],
-            blocked: vec![false; (ARENA_WIDTH * ARENA_HEIGHT) as usize],
-            tile_content: vec![Vec::new(); (ARENA_WIDTH * ARENA_HEIGHT) as usize],
+            room_info: Vec::new(),
+            width,
+            height,
+            revealed_tiles: vec![false; size],
+            visible_tiles: vec![false; size],
+            blocked: vec![false; size],
+            tile_content: vec![Vec::new(); size],
         }
     }
-}
 
-impl Map {
     pub fn xy_idx(&self, x: i32, y: i32) -> usize {
         ((y + self.height / 2) * self.width + x + self.width / 2) as usize
     }
@@ -186,19 +356,151 @@ impl Map {
         (x, y)
     }
 
-    pub fn populate_blocked(&mut self) {
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        let (min_x, min_y) = (-self.width / 2, -self.height / 2);
+        x >= min_x && x < min_x + self.width && y >= min_y && y < min_y + self.height
+    }
+
+    /// The tile at `(x, y)`, or `None` off the edge of the map.
+    pub fn get(&self, x: i32, y: i32) -> Option<TileType> {
+        if self.in_bounds(x, y) {
+            Some(self.tiles[self.xy_idx(x, y)])
+        } else {
+            None
+        }
+    }
+
+    /// Whether `(x, y)` is on the map and not blocked.
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        self.in_bounds(x, y) && !self.blocked[self.xy_idx(x, y)]
+    }
+
+    /// Unblocked orthogonal neighbours of `(x, y)` with the cost of stepping to them.
+    pub fn neighbours_4(&self, (x, y): (i32, i32)) -> impl Iterator<Item = ((i32, i32), f32)> + '_ {
+        ORTHOGONAL_DIRECTIONS
+            .iter()
+            .map(move |(dx, dy)| (x + dx, y + dy))
+            .filter(move |&(nx, ny)| self.is_walkable(nx, ny))
+            .map(|point| (point, 1.0))
+    }
+
+    /// Unblocked neighbours of `(x, y)` including diagonals, with the cost of stepping to them.
+    /// Diagonal steps can't cut corners, so both tiles beside the step have to be open.
+    pub fn neighbours_8(&self, (x, y): (i32, i32)) -> impl Iterator<Item = ((i32, i32), f32)> + '_ {
+        let diagonals = DIAGONAL_DIRECTIONS
+            .iter()
+            .filter(move |(dx, dy)| {
+                self.is_walkable(x + dx, y + dy) && self.is_walkable(x + dx, y) && self.is_walkable(x, y + dy)
+            })
+            .map(move |(dx, dy)| ((x + dx, y + dy), DIAGONAL_COST));
+        self.neighbours_4((x, y)).chain(diagonals)
+    }
+
+    /// Shortest path from `from` to `to`, moving in 8 directions or only orthogonally, as the
+    /// points to step through after `from`, ending with `to`. `to` itself may be blocked, so
+    /// something can path up to whatever is standing there.
+    pub fn find_path(&self, from: (i32, i32), to: (i32, i32), diagonals: bool) -> Option<Vec<(i32, i32)>> {
+        if !self.in_bounds(from.0, from.1) || !self.in_bounds(to.0, to.1) {
+            return None;
+        }
+        if from == to {
+            return Some(Vec::new());
+        }
+        let heuristic = |(x, y): (i32, i32)| {
+            let (dx, dy) = ((x - to.0).abs() as f32, (y - to.1).abs() as f32);
+            if diagonals {
+                dx.max(dy) + (DIAGONAL_COST - 1.0) * dx.min(dy)
+            } else {
+                dx + dy
+            }
+        };
+
+        let start = self.xy_idx(from.0, from.1);
+        let goal = self.xy_idx(to.0, to.1);
+        let mut costs = vec![f32::MAX; self.tiles.len()];
+        let mut came_from = vec![None; self.tiles.len()];
+        let mut open = BinaryHeap::new();
+        costs[start] = 0.0;
+        open.push(OpenNode {
+            value: heuristic(from),
+            idx: start,
+        });
+
+        while let Some(OpenNode { idx, .. }) = open.pop() {
+            if idx == goal {
+                let mut path = vec![to];
+                let mut current = goal;
+                while let Some(previous) = came_from[current] {
+                    if previous == start {
+                        break;
+                    }
+                    path.push(self.idx_xy(previous));
+                    current = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let point = self.idx_xy(idx);
+            let mut steps: Vec<_> = if diagonals {
+                self.neighbours_8(point).collect()
+            } else {
+                self.neighbours_4(point).collect()
+            };
+            if let Some(step) = self.step_onto_goal(point, to, diagonals) {
+                steps.push(step);
+            }
+            for (next, step_cost) in steps {
+                let next_idx = self.xy_idx(next.0, next.1);
+                let cost = costs[idx] + step_cost;
+                if cost < costs[next_idx] {
+                    costs[next_idx] = cost;
+                    came_from[next_idx] = Some(idx);
+                    open.push(OpenNode {
+                        value: cost + heuristic(next),
+                        idx: next_idx,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// The step from `point` onto a blocked `goal` next to it, which the neighbour iterators
+    /// leave out.
+    fn step_onto_goal(&self, point: (i32, i32), goal: (i32, i32), diagonals: bool) -> Option<((i32, i32), f32)> {
+        let (dx, dy) = (goal.0 - point.0, goal.1 - point.1);
+        if dx.abs() > 1 || dy.abs() > 1 || self.is_walkable(goal.0, goal.1) {
+            return None;
+        }
+        if dx == 0 || dy == 0 {
+            Some((goal, 1.0))
+        } else if diagonals && self.is_walkable(point.0 + dx, point.1) && self.is_walkable(point.0, point.1 + dy) {
+            Some((goal, DIAGONAL_COST))
+        } else {
+            None
+        }
+    }
+
+    pub fn populate_blocked(&mut self, terrain: &TerrainTable) {
         for (i, tile) in self.tiles.iter_mut().enumerate() {
-            self.blocked[i] = *tile == TileType::Wall;
+            self.blocked[i] = terrain.get(*tile).blocks;
         }
     }
 
+    /// Changes the terrain at `idx`, keeping `blocked` in step with it.
+    pub fn set_tile(&mut self, idx: usize, tile: TileType, terrain: &TerrainTable) {
+        self.tiles[idx] = tile;
+        self.blocked[idx] = terrain.get(tile).blocks;
+    }
+
     pub fn clear_content_index(&mut self) {
         for content in self.tile_content.iter_mut() {
             content.clear();
         }
     }
 
-    fn apply_room(&mut self, room: &rect::Rect) {
+    pub fn apply_room(&mut self, room: &rect::Rect) {
         for y in room.y1 + 1..=room.y2 {
             for x in room.x1 + 1..=room.x2 {
                 let idx = self.xy_idx(x, y);
@@ -207,7 +509,7 @@ impl Map {
         }
     }
 
-    fn apply_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
+    pub fn apply_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
         for x in min(x1, x2)..=max(x1, x2) {
             let idx = self.xy_idx(x, y);
             if idx > 0 && idx < self.width as usize * self.height as usize {
@@ -215,7 +517,7 @@ impl Map {
             }
         }
     }
-    fn apply_vertical_tunnel(&mut self, y1: i32, y2: i32, x: i32) {
+    pub fn apply_vertical_tunnel(&mut self, y1: i32, y2: i32, x: i32) {
         for y in min(y1, y2)..=max(y1, y2) {
             let idx = self.xy_idx(x, y);
             if idx > 0 && idx < self.width as usize * self.height as usize {
@@ -0,0 +1,211 @@
+use std::fmt::Write;
+
+use rand::rngs::StdRng;
+
+use super::common::{floor_closest_to_center, Snapshots};
+use super::{MapBuild, MapBuilder};
+use crate::map::{Map, TileType};
+
+/// Separates the header from the rows of the map.
+const HEADER_END: &str = "---";
+
+/// Characters used for each tile in the text format. These are fixed rather than taken from
+/// `terrain.ron`, so maps keep reading the same if a glyph there changes.
+const TILE_CHARS: [(TileType, char); 16] = [
+    (TileType::Floor, '.'),
+    (TileType::Wall, '#'),
+    (TileType::DownStairs, '>'),
+    (TileType::UpStairs, '<'),
+    (TileType::ClosedDoor, '+'),
+    (TileType::OpenDoor, '\''),
+    (TileType::ShallowWater, '~'),
+    (TileType::DeepWater, '='),
+    (TileType::Lava, '^'),
+    (TileType::TallGrass, '"'),
+    (TileType::Rubble, ','),
+    (TileType::Chasm, ':'),
+    (TileType::Bookshelf, '&'),
+    (TileType::Bed, '-'),
+    (TileType::Altar, '_'),
+    (TileType::Pillar, '|'),
+];
+
+/// Reads a level in the text format:
+///
+/// ```text
+/// width 6
+/// height 3
+/// player 1 1
+/// spawn 3 1 Goblin
+/// ---
+/// ######
+/// #...>#
+/// ######
+/// ```
+///
+/// Rows run from the top of the map down. `player` and `spawn` lines give a column and a row
+/// counted from the top left, so the player and spawns keep whatever tile is under them, and a
+/// `spawn` can share its tile with another one. Names can have spaces in.
+pub fn parse_ascii(text: &str) -> MapBuild {
+    let mut lines = text.lines();
+    let (mut width, mut height) = (None, None);
+    let mut player = None;
+    let mut spawns = Vec::new();
+    for line in lines.by_ref() {
+        let line = line.trim();
+        if line == HEADER_END {
+            break;
+        }
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("width") => width = Some(parse_number(words.next(), "width")),
+            Some("height") => height = Some(parse_number(words.next(), "height")),
+            Some("player") => {
+                player = Some((
+                    parse_number(words.next(), "player column"),
+                    parse_number(words.next(), "player row"),
+                ))
+            }
+            Some("spawn") => {
+                let column = parse_number(words.next(), "spawn column");
+                let row = parse_number(words.next(), "spawn row");
+                let name = words.collect::<Vec<_>>().join(" ");
+                assert!(!name.is_empty(), "spawn needs a name");
+                spawns.push(((column, row), name));
+            }
+            Some(other) => panic!("Unknown map header line {}", other),
+            None => continue,
+        }
+    }
+    let width = width.expect("Map has no width");
+    let height = height.expect("Map has no height");
+
+    let rows: Vec<&str> = lines.collect();
+    assert_eq!(rows.len() as i32, height, "Map should have {} rows", height);
+
+    let mut map = Map::new(width, height);
+    for (row, line) in rows.iter().enumerate() {
+        assert_eq!(line.chars().count() as i32, width, "Map row {} should be {} wide", row, width);
+        for (column, c) in line.chars().enumerate() {
+            let idx = grid_idx(&map, column as i32, row as i32);
+            map.tiles[idx] = TILE_CHARS
+                .iter()
+                .find(|(_, tile_char)| *tile_char == c)
+                .map(|(tile, _)| *tile)
+                .unwrap_or_else(|| panic!("Unknown map character {:?}", c));
+        }
+    }
+
+    let player_start = match player {
+        Some((column, row)) => map.idx_xy(grid_idx(&map, column, row)),
+        None => floor_closest_to_center(&map),
+    };
+    let named_spawns = spawns
+        .into_iter()
+        .map(|((column, row), name)| (map.idx_xy(grid_idx(&map, column, row)), name))
+        .collect();
+    MapBuild {
+        map,
+        player_start,
+        spawn_points: Vec::new(),
+        named_spawns,
+    }
+}
+
+/// Writes `map` in the format read by `parse_ascii`, with the player and named spawns listed in
+/// the header.
+pub fn to_ascii(map: &Map, player: (i32, i32), spawns: &[((i32, i32), String)]) -> String {
+    let mut text = String::new();
+    writeln!(text, "width {}", map.width).unwrap();
+    writeln!(text, "height {}", map.height).unwrap();
+    let (column, row) = grid_position(map, player);
+    writeln!(text, "player {} {}", column, row).unwrap();
+    for (point, name) in spawns.iter() {
+        let (column, row) = grid_position(map, *point);
+        writeln!(text, "spawn {} {} {}", column, row, name).unwrap();
+    }
+    writeln!(text, "{}", HEADER_END).unwrap();
+    for row in 0..map.height {
+        for column in 0..map.width {
+            text.push(tile_char(map.tiles[grid_idx(map, column, row)]));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Index of the tile at `column` and `row` counted from the top left, as the text is laid out.
+fn grid_idx(map: &Map, column: i32, row: i32) -> usize {
+    assert!(
+        column >= 0 && row >= 0 && column < map.width && row < map.height,
+        "{} {} is off the map",
+        column,
+        row
+    );
+    ((map.height - 1 - row) * map.width + column) as usize
+}
+
+fn grid_position(map: &Map, (x, y): (i32, i32)) -> (i32, i32) {
+    let idx = map.xy_idx(x, y) as i32;
+    (idx % map.width, map.height - 1 - idx / map.width)
+}
+
+fn tile_char(tile: TileType) -> char {
+    TILE_CHARS
+        .iter()
+        .find(|(tile_type, _)| *tile_type == tile)
+        .map(|(_, c)| *c)
+        .unwrap()
+}
+
+fn parse_number(word: Option<&str>, field: &str) -> i32 {
+    word.and_then(|word| word.parse().ok())
+        .unwrap_or_else(|| panic!("Map {} should be a number", field))
+}
+
+/// Loads a level from a text file in `assets/maps` instead of generating one.
+pub struct AsciiMapBuilder {
+    path: String,
+}
+
+impl AsciiMapBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            path: format!("assets/maps/{}.txt", name),
+        }
+    }
+}
+
+impl MapBuilder for AsciiMapBuilder {
+    fn build(&mut self, _rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild {
+        let text = std::fs::read_to_string(&self.path).unwrap_or_else(|_| panic!("Unable to read {}", self.path));
+        let build = parse_ascii(&text);
+        snapshots.take(&build.map);
+        build
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_maps_read_back_the_same() {
+        let text = std::fs::read_to_string("assets/maps/test_room.txt").unwrap();
+        let build = parse_ascii(&text);
+        assert_eq!(to_ascii(&build.map, build.player_start, &build.named_spawns), text);
+    }
+
+    #[test]
+    fn tiles_under_the_player_and_spawns_are_kept() {
+        let text = "width 6\nheight 3\nplayer 1 1\nspawn 2 1 Goblin\nspawn 2 1 Health Potion\nspawn 3 1 Orc\n\
+                    ---\n######\n#<~+>#\n######\n";
+        let build = parse_ascii(text);
+        let (x, y) = build.player_start;
+        assert_eq!(build.map.get(x, y), Some(TileType::UpStairs));
+        let ((x, y), _) = build.named_spawns[0];
+        assert_eq!(build.map.get(x, y), Some(TileType::ShallowWater));
+        assert_eq!(build.named_spawns[0].0, build.named_spawns[1].0);
+        assert_eq!(to_ascii(&build.map, build.player_start, &build.named_spawns), text);
+    }
+}
@@ -0,0 +1,158 @@
+use rand::prelude::*;
+
+use super::common::Snapshots;
+use super::corridors::{add_loops, dig, CorridorSettings, CorridorShape};
+use super::{MapBuild, MapBuilder};
+use crate::map::Map;
+use crate::rect::Rect;
+
+/// Smallest partition side, counting the wall on either side of its room.
+const MIN_PARTITION: i32 = 8;
+const MIN_ROOM_SIZE: i32 = 4;
+
+/// Recursively splits the arena in two, places one room in every leaf partition and
+/// joins the rooms of sibling partitions, so the level is connected by construction. The
+/// corridor layout setting is ignored, as the partitions decide which rooms are joined.
+pub struct BspDungeonBuilder {
+    width: i32,
+    height: i32,
+    corridors: CorridorSettings,
+}
+
+impl BspDungeonBuilder {
+    pub fn new(width: i32, height: i32, corridors: CorridorSettings) -> Self {
+        Self {
+            width,
+            height,
+            corridors,
+        }
+    }
+
+    /// Returns the rooms placed inside `partition`, whose tiles span `x1..=x2`, `y1..=y2`. The
+    /// pairs of rooms given corridors go in `joined`, numbered from `offset`, the index the first
+    /// of these rooms will have in the level's room list.
+    fn split(
+        &self,
+        map: &mut Map,
+        partition: Rect,
+        offset: usize,
+        joined: &mut Vec<(usize, usize)>,
+        rng: &mut StdRng,
+        snapshots: &mut Snapshots,
+    ) -> Vec<Rect> {
+        let width = partition.x2 - partition.x1 + 1;
+        let height = partition.y2 - partition.y1 + 1;
+        let can_split_vertically = width >= MIN_PARTITION * 2;
+        let can_split_horizontally = height >= MIN_PARTITION * 2;
+
+        if !can_split_vertically && !can_split_horizontally {
+            let room = place_room(map, &partition, rng);
+            snapshots.take(map);
+            return vec![room];
+        }
+
+        // Prefer cutting across the longer side so partitions stay roughly square
+        let vertical = if can_split_vertically && can_split_horizontally {
+            if width as f32 / height as f32 >= 1.25 {
+                true
+            } else if height as f32 / width as f32 >= 1.25 {
+                false
+            } else {
+                rng.gen()
+            }
+        } else {
+            can_split_vertically
+        };
+
+        let (first, second) = if vertical {
+            let split = rng.gen_range((partition.x1 + MIN_PARTITION - 1)..=(partition.x2 - MIN_PARTITION));
+            (
+                Rect { x2: split, ..partition },
+                Rect { x1: split + 1, ..partition },
+            )
+        } else {
+            let split = rng.gen_range((partition.y1 + MIN_PARTITION - 1)..=(partition.y2 - MIN_PARTITION));
+            (
+                Rect { y2: split, ..partition },
+                Rect { y1: split + 1, ..partition },
+            )
+        };
+
+        let mut rooms = self.split(map, first, offset, joined, rng, snapshots);
+        let second_offset = offset + rooms.len();
+        let second_rooms = self.split(map, second, second_offset, joined, rng, snapshots);
+        if let Some((a, b)) = connect_closest(map, &rooms, &second_rooms, self.corridors.shape, rng) {
+            joined.push((offset + a, second_offset + b));
+        }
+        snapshots.take(map);
+        rooms.extend(second_rooms);
+        rooms
+    }
+}
+
+impl MapBuilder for BspDungeonBuilder {
+    fn build(&mut self, rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild {
+        let mut map = Map::new(self.width, self.height);
+        let arena = Rect {
+            x1: -self.width / 2,
+            x2: self.width - self.width / 2 - 1,
+            y1: -self.height / 2,
+            y2: self.height - self.height / 2 - 1,
+        };
+
+        let mut joined = Vec::new();
+        let rooms = self.split(&mut map, arena, 0, &mut joined, rng, snapshots);
+        add_loops(&mut map, &rooms, &mut joined, &self.corridors, rng, snapshots);
+
+        let player_start = rooms[0].center();
+        let spawn_points = rooms.iter().skip(1).map(|room| room.center()).collect();
+        map.rooms = rooms;
+
+        MapBuild {
+            map,
+            player_start,
+            spawn_points,
+            named_spawns: Vec::new(),
+        }
+    }
+}
+
+/// Carves a random room that leaves a wall on every side of `partition`.
+fn place_room(map: &mut Map, partition: &Rect, rng: &mut StdRng) -> Rect {
+    let max_w = partition.x2 - partition.x1 - 1;
+    let max_h = partition.y2 - partition.y1 - 1;
+    let w = rng.gen_range(MIN_ROOM_SIZE..=max_w);
+    let h = rng.gen_range(MIN_ROOM_SIZE..=max_h);
+    let x = rng.gen_range(partition.x1..=(partition.x2 - 1 - w));
+    let y = rng.gen_range(partition.y1..=(partition.y2 - 1 - h));
+
+    let room = Rect::new(x, y, w, h);
+    map.apply_room(&room);
+    room
+}
+
+/// Joins the closest pair of rooms between two sibling partitions, returning their indices in
+/// `first` and `second`.
+fn connect_closest(
+    map: &mut Map,
+    first: &[Rect],
+    second: &[Rect],
+    shape: CorridorShape,
+    rng: &mut StdRng,
+) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize, i32)> = None;
+    for (i, a) in first.iter().enumerate() {
+        for (j, b) in second.iter().enumerate() {
+            let (ax, ay) = a.center();
+            let (bx, by) = b.center();
+            let distance = (ax - bx).abs() + (ay - by).abs();
+            if best.map_or(true, |(_, _, best_distance)| distance < best_distance) {
+                best = Some((i, j, distance));
+            }
+        }
+    }
+
+    let (i, j, _) = best?;
+    dig(map, first[i].center(), second[j].center(), shape, rng);
+    Some((i, j))
+}
@@ -0,0 +1,77 @@
+use rand::prelude::*;
+
+use super::common::{floor_closest_to_center, keep_largest_region, spawn_points_by_area, Snapshots};
+use super::{MapBuild, MapBuilder};
+use crate::map::{Map, TileType};
+
+const FLOOR_CHANCE: f64 = 0.55;
+const SMOOTHING_PASSES: i32 = 15;
+
+/// Organic caverns grown from random noise.
+pub struct CellularAutomataBuilder {
+    width: i32,
+    height: i32,
+}
+
+impl CellularAutomataBuilder {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self { width, height }
+    }
+}
+
+impl MapBuilder for CellularAutomataBuilder {
+    fn build(&mut self, rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild {
+        let mut map = Map::new(self.width, self.height);
+
+        // Random noise, leaving the outer edge solid
+        for y in 1..map.height - 1 {
+            for x in 1..map.width - 1 {
+                let idx = (y * map.width + x) as usize;
+                map.tiles[idx] = if rng.gen_bool(FLOOR_CHANCE) {
+                    TileType::Floor
+                } else {
+                    TileType::Wall
+                };
+            }
+        }
+        snapshots.take(&map);
+
+        for _ in 0..SMOOTHING_PASSES {
+            let mut smoothed = map.tiles.clone();
+            for y in 1..map.height - 1 {
+                for x in 1..map.width - 1 {
+                    let idx = (y * map.width + x) as usize;
+                    let mut walls = 0;
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            if (dx != 0 || dy != 0)
+                                && map.tiles[((y + dy) * map.width + x + dx) as usize] == TileType::Wall
+                            {
+                                walls += 1;
+                            }
+                        }
+                    }
+                    smoothed[idx] = if walls > 4 || walls == 0 {
+                        TileType::Wall
+                    } else {
+                        TileType::Floor
+                    };
+                }
+            }
+            map.tiles = smoothed;
+            snapshots.take(&map);
+        }
+
+        keep_largest_region(&mut map);
+
+        let player_start = floor_closest_to_center(&map);
+        let spawn_points = spawn_points_by_area(&map, rng, player_start);
+
+        MapBuild {
+            map,
+            player_start,
+            spawn_points,
+            named_spawns: Vec::new(),
+        }
+    }
+}
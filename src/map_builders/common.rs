@@ -0,0 +1,409 @@
+use std::collections::VecDeque;
+
+use rand::prelude::*;
+use serde::Deserialize;
+
+use super::MapBuild;
+use crate::dijkstra::DijkstraMap;
+use crate::map::{Map, TileType};
+
+/// Side of the square areas used to spread spawns over levels that have no rooms.
+const SPAWN_AREA_SIZE: i32 = 16;
+/// Spawns closer than this to the player start are skipped.
+const MIN_SPAWN_DISTANCE: i32 = 8;
+/// Items left lying around in rooms, by relative weight.
+const ITEM_TABLE: [(&str, u32); 6] = [
+    ("Health Potion", 6),
+    ("Magic Missile Scroll", 3),
+    ("Fireball Scroll", 1),
+    ("Confusion Scroll", 2),
+    ("Dagger", 1),
+    ("Shield", 1),
+];
+/// How likely each room is to have an item lying in it.
+const ROOM_ITEM_CHANCE: f64 = 0.3;
+
+/// Copies of `Map::tiles` taken after each step of building a level, so generation can be
+/// watched played back. Taking one does nothing unless recording.
+#[derive(Default)]
+pub struct Snapshots {
+    recording: bool,
+    pub frames: Vec<Vec<TileType>>,
+}
+
+impl Snapshots {
+    pub fn new(recording: bool) -> Self {
+        Self {
+            recording,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn take(&mut self, map: &Map) {
+        if self.recording {
+            self.frames.push(map.tiles.clone());
+        }
+    }
+}
+
+/// Returns the index of every non-wall tile 4-connected to `start`.
+pub fn flood_fill(map: &Map, start: usize) -> Vec<usize> {
+    let mut seen = vec![false; map.tiles.len()];
+    let mut region = Vec::new();
+    let mut stack = vec![start];
+    seen[start] = true;
+
+    while let Some(idx) = stack.pop() {
+        region.push(idx);
+        let x = idx as i32 % map.width;
+        let y = idx as i32 / map.width;
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= map.width || ny >= map.height {
+                continue;
+            }
+            let neighbour = (ny * map.width + nx) as usize;
+            if !seen[neighbour] && map.tiles[neighbour] != TileType::Wall {
+                seen[neighbour] = true;
+                stack.push(neighbour);
+            }
+        }
+    }
+
+    region
+}
+
+/// Walls off every open region except the largest one.
+pub fn keep_largest_region(map: &mut Map) {
+    let mut assigned = vec![false; map.tiles.len()];
+    let mut largest: Vec<usize> = Vec::new();
+    for idx in 0..map.tiles.len() {
+        if assigned[idx] || map.tiles[idx] == TileType::Wall {
+            continue;
+        }
+        let region = flood_fill(map, idx);
+        for &i in region.iter() {
+            assigned[i] = true;
+        }
+        if region.len() > largest.len() {
+            largest = region;
+        }
+    }
+
+    let mut keep = vec![false; map.tiles.len()];
+    for &i in largest.iter() {
+        keep[i] = true;
+    }
+    for (idx, tile) in map.tiles.iter_mut().enumerate() {
+        if !keep[idx] {
+            *tile = TileType::Wall;
+        }
+    }
+}
+
+/// The floor tile nearest to the middle of the map.
+pub fn floor_closest_to_center(map: &Map) -> (i32, i32) {
+    map.tiles
+        .iter()
+        .enumerate()
+        .filter(|(_, tile)| **tile == TileType::Floor)
+        .map(|(idx, _)| map.idx_xy(idx))
+        .min_by_key(|(x, y)| x * x + y * y)
+        .expect("Map has no floor")
+}
+
+/// Picks one random floor tile in each square area of the map, for levels without rooms.
+pub fn spawn_points_by_area(map: &Map, rng: &mut StdRng, player_start: (i32, i32)) -> Vec<(i32, i32)> {
+    let areas_wide = (map.width + SPAWN_AREA_SIZE - 1) / SPAWN_AREA_SIZE;
+    let areas_high = (map.height + SPAWN_AREA_SIZE - 1) / SPAWN_AREA_SIZE;
+    let mut areas: Vec<Vec<(i32, i32)>> = vec![Vec::new(); (areas_wide * areas_high) as usize];
+
+    for (idx, tile) in map.tiles.iter().enumerate() {
+        if *tile != TileType::Floor {
+            continue;
+        }
+        let (x, y) = map.idx_xy(idx);
+        if (x - player_start.0).abs() + (y - player_start.1).abs() < MIN_SPAWN_DISTANCE {
+            continue;
+        }
+        let area = (idx as i32 / map.width / SPAWN_AREA_SIZE) * areas_wide
+            + (idx as i32 % map.width) / SPAWN_AREA_SIZE;
+        areas[area as usize].push((x, y));
+    }
+
+    areas.iter().filter_map(|area| area.choose(rng).copied()).collect()
+}
+
+pub struct MapStats {
+    pub open_tiles: usize,
+    /// Steps from the player start to the furthest reachable tile.
+    pub longest_path: i32,
+}
+
+/// Walls off every open tile that can't be walked to from the player start, drops spawns that
+/// were on them and measures what is left. Closed doors count as open.
+pub fn cull_unreachable(build: &mut MapBuild) -> MapStats {
+    let map = &mut build.map;
+    let mut distances: Vec<Option<i32>> = vec![None; map.tiles.len()];
+    let start = map.xy_idx(build.player_start.0, build.player_start.1);
+    distances[start] = Some(0);
+
+    let mut open_tiles = 0;
+    let mut longest_path = 0;
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+    while let Some(idx) = queue.pop_front() {
+        let distance = distances[idx].unwrap();
+        open_tiles += 1;
+        longest_path = longest_path.max(distance);
+
+        let x = idx as i32 % map.width;
+        let y = idx as i32 / map.width;
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= map.width || ny >= map.height {
+                continue;
+            }
+            let neighbour = (ny * map.width + nx) as usize;
+            if distances[neighbour].is_none() && map.tiles[neighbour] != TileType::Wall {
+                distances[neighbour] = Some(distance + 1);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    for (idx, tile) in map.tiles.iter_mut().enumerate() {
+        if distances[idx].is_none() {
+            *tile = TileType::Wall;
+        }
+    }
+    build
+        .spawn_points
+        .retain(|&(x, y)| distances[map.xy_idx(x, y)].is_some());
+    build
+        .named_spawns
+        .retain(|&((x, y), _)| distances[map.xy_idx(x, y)].is_some());
+
+    MapStats {
+        open_tiles,
+        longest_path,
+    }
+}
+
+/// Sprinkles patches of grass and rubble, and a few pools, lava vents and chasms, over open floor.
+/// Impassable terrain is only ever placed as single tiles ringed by open floor, so it can
+/// never cut a level in two. Nothing is placed under the player or anything that spawns.
+pub fn scatter_terrain(build: &mut MapBuild, rng: &mut StdRng) {
+    let map = &mut build.map;
+    let mut reserved = vec![false; map.tiles.len()];
+    let spawns = build.named_spawns.iter().map(|(point, _)| point);
+    for &(x, y) in build.spawn_points.iter().chain(spawns).chain(std::iter::once(&build.player_start)) {
+        reserved[map.xy_idx(x, y)] = true;
+    }
+    let patch_count = map.tiles.len() / 400;
+
+    for _ in 0..patch_count {
+        let (cx, cy) = (rng.gen_range(0..map.width), rng.gen_range(0..map.height));
+        let (tile, radius) = if rng.gen_bool(0.6) {
+            (TileType::TallGrass, rng.gen_range(2..=4))
+        } else {
+            (TileType::Rubble, rng.gen_range(1..=2))
+        };
+        for y in cy - radius..=cy + radius {
+            for x in cx - radius..=cx + radius {
+                if x < 0 || y < 0 || x >= map.width || y >= map.height {
+                    continue;
+                }
+                let idx = (y * map.width + x) as usize;
+                let in_patch = (x - cx).pow(2) + (y - cy).pow(2) <= radius * radius;
+                if in_patch && map.tiles[idx] == TileType::Floor && !reserved[idx] && rng.gen_bool(0.7) {
+                    map.tiles[idx] = tile;
+                }
+            }
+        }
+    }
+
+    for _ in 0..patch_count / 2 {
+        let x = rng.gen_range(1..map.width - 1);
+        let y = rng.gen_range(1..map.height - 1);
+        let idx = (y * map.width + x) as usize;
+        let surrounded_by_floor = (-1..=1).all(|dy| {
+            (-1..=1).all(|dx| map.tiles[((y + dy) * map.width + x + dx) as usize] == TileType::Floor)
+        });
+        if !surrounded_by_floor || reserved[idx] {
+            continue;
+        }
+
+        match rng.gen_range(0..4) {
+            0 => map.tiles[idx] = TileType::Lava,
+            1 => map.tiles[idx] = TileType::Chasm,
+            _ => {
+                map.tiles[idx] = TileType::DeepWater;
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let ring_idx = ((y + dy) * map.width + x + dx) as usize;
+                        if ring_idx != idx && !reserved[ring_idx] {
+                            map.tiles[ring_idx] = TileType::ShallowWater;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Leaves an item lying on the floor of some rooms, away from anything else that spawns.
+pub fn scatter_items(build: &mut MapBuild, rng: &mut StdRng) {
+    let mut taken: Vec<(i32, i32)> = build
+        .spawn_points
+        .iter()
+        .copied()
+        .chain(build.named_spawns.iter().map(|(point, _)| *point))
+        .collect();
+    taken.push(build.player_start);
+
+    for room in build.map.rooms.iter() {
+        if !rng.gen_bool(ROOM_ITEM_CHANCE) {
+            continue;
+        }
+        let free: Vec<(i32, i32)> = (room.y1 + 1..=room.y2)
+            .flat_map(|y| (room.x1 + 1..=room.x2).map(move |x| (x, y)))
+            .filter(|&(x, y)| {
+                matches!(
+                    build.map.get(x, y),
+                    Some(TileType::Floor) | Some(TileType::TallGrass) | Some(TileType::Rubble)
+                ) && !taken.contains(&(x, y))
+            })
+            .collect();
+        if let Some(&point) = free.choose(rng) {
+            let (name, _) = ITEM_TABLE.choose_weighted(rng, |(_, weight)| *weight).unwrap();
+            build.named_spawns.push((point, name.to_string()));
+            taken.push(point);
+        }
+    }
+}
+
+/// Closes off every gap a corridor has cut in a room's wall with a door.
+pub fn place_doors(map: &mut Map) {
+    let mut doors = Vec::new();
+    for room in map.rooms.iter() {
+        // Rooms are carved from x1 + 1 to x2, so their walls run along x1 and x2 + 1
+        for x in room.x1 + 1..=room.x2 {
+            for &y in [room.y1, room.y2 + 1].iter() {
+                if is_doorway(map, x, y, (1, 0)) {
+                    doors.push(map.xy_idx(x, y));
+                }
+            }
+        }
+        for y in room.y1 + 1..=room.y2 {
+            for &x in [room.x1, room.x2 + 1].iter() {
+                if is_doorway(map, x, y, (0, 1)) {
+                    doors.push(map.xy_idx(x, y));
+                }
+            }
+        }
+    }
+
+    for idx in doors {
+        map.tiles[idx] = TileType::ClosedDoor;
+    }
+}
+
+/// A floor tile in a wall, with wall on both sides along the wall's direction.
+fn is_doorway(map: &Map, x: i32, y: i32, (along_x, along_y): (i32, i32)) -> bool {
+    let in_bounds = |x: i32, y: i32| {
+        x >= -map.width / 2 && x < map.width - map.width / 2 && y >= -map.height / 2 && y < map.height - map.height / 2
+    };
+    if !in_bounds(x - along_x, y - along_y) || !in_bounds(x + along_x, y + along_y) {
+        return false;
+    }
+
+    map.tiles[map.xy_idx(x, y)] == TileType::Floor
+        && map.tiles[map.xy_idx(x - along_x, y - along_y)] == TileType::Wall
+        && map.tiles[map.xy_idx(x + along_x, y + along_y)] == TileType::Wall
+}
+
+/// Puts the way down as far a walk as possible from the first room, or from the player on
+/// levels without rooms. Below the first level the player arrives on a way back up.
+pub fn place_stairs(build: &mut MapBuild, depth: i32) {
+    let map = &mut build.map;
+    let (start_x, start_y) = build.player_start;
+
+    let (origin_x, origin_y) = map.rooms.first().map_or(build.player_start, |room| room.center());
+    let origin = map.xy_idx(origin_x, origin_y);
+    let distances = DijkstraMap::with_costs(map, &[origin], f32::MAX, |idx| {
+        if map.tiles[idx] == TileType::Wall {
+            None
+        } else {
+            Some(1.0)
+        }
+    });
+    let exit = distances
+        .furthest(|idx| map.tiles[idx] == TileType::Floor)
+        .expect("Map has no floor");
+    map.tiles[exit] = TileType::DownStairs;
+
+    if depth > 1 {
+        let entrance = map.xy_idx(start_x, start_y);
+        map.tiles[entrance] = TileType::UpStairs;
+    }
+}
+
+/// Mirroring applied when painting floor, so organic builders can produce symmetric levels.
+#[derive(Deserialize, Copy, Clone, Debug)]
+pub enum Symmetry {
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+/// Carves floor with a square brush at the array coordinates `(ax, ay)` and its mirror images.
+/// Returns how many tiles were newly opened.
+pub fn paint(map: &mut Map, symmetry: Symmetry, brush_size: i32, ax: i32, ay: i32) -> usize {
+    let mirror_x = map.width - 1 - ax;
+    let mirror_y = map.height - 1 - ay;
+    match symmetry {
+        Symmetry::None => apply_brush(map, brush_size, ax, ay),
+        Symmetry::Horizontal => apply_brush(map, brush_size, ax, ay) + apply_brush(map, brush_size, mirror_x, ay),
+        Symmetry::Vertical => apply_brush(map, brush_size, ax, ay) + apply_brush(map, brush_size, ax, mirror_y),
+        Symmetry::Both => {
+            apply_brush(map, brush_size, ax, ay)
+                + apply_brush(map, brush_size, mirror_x, ay)
+                + apply_brush(map, brush_size, ax, mirror_y)
+                + apply_brush(map, brush_size, mirror_x, mirror_y)
+        }
+    }
+}
+
+fn apply_brush(map: &mut Map, brush_size: i32, ax: i32, ay: i32) -> usize {
+    let size = brush_size.max(1);
+    let mut carved = 0;
+    for y in ay - size / 2..ay - size / 2 + size {
+        for x in ax - size / 2..ax - size / 2 + size {
+            // Never carve the outer edge
+            if x > 0 && x < map.width - 1 && y > 0 && y < map.height - 1 {
+                let idx = (y * map.width + x) as usize;
+                if map.tiles[idx] != TileType::Floor {
+                    map.tiles[idx] = TileType::Floor;
+                    carved += 1;
+                }
+            }
+        }
+    }
+    carved
+}
+
+pub fn floor_count(map: &Map) -> usize {
+    map.tiles.iter().filter(|tile| **tile == TileType::Floor).count()
+}
+
+/// Moves the array coordinates one step in a random cardinal direction, staying off the outer edge.
+pub fn random_step(map: &Map, rng: &mut StdRng, ax: &mut i32, ay: &mut i32) {
+    match rng.gen_range(0..4) {
+        0 => *ax = (*ax - 1).max(1),
+        1 => *ax = (*ax + 1).min(map.width - 2),
+        2 => *ay = (*ay - 1).max(1),
+        _ => *ay = (*ay + 1).min(map.height - 2),
+    }
+}
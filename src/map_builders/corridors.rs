@@ -0,0 +1,191 @@
+use rand::prelude::*;
+use serde::Deserialize;
+
+use super::common::Snapshots;
+use crate::map::{Map, TileType};
+use crate::rect::Rect;
+
+/// Which rooms get joined to which.
+#[derive(Deserialize, Copy, Clone, Debug)]
+pub enum CorridorLayout {
+    /// Each room to the one created before it.
+    Sequential,
+    /// Each room to the closest of the rooms created before it.
+    Nearest,
+    /// The shortest set of corridors that reaches every room.
+    SpanningTree,
+}
+
+/// How a single corridor is dug between two points.
+#[derive(Deserialize, Copy, Clone, Debug)]
+pub enum CorridorShape {
+    /// One horizontal and one vertical leg, in a random order.
+    LShaped,
+    /// A Bresenham line, widened where it steps diagonally so it can be walked.
+    Straight,
+    /// A random walk that drifts towards its end.
+    Drunken,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct CorridorSettings {
+    pub layout: CorridorLayout,
+    pub shape: CorridorShape,
+    /// Corridors added between nearby rooms that aren't joined yet, so levels have loops.
+    pub extra_loops: i32,
+}
+
+/// How likely a drunken corridor is to step towards its end rather than in a random direction.
+const DRUNKEN_BIAS: f64 = 0.6;
+
+/// Joins `rooms` with corridors as `settings` asks.
+pub fn connect_rooms(map: &mut Map, rooms: &[Rect], settings: &CorridorSettings, rng: &mut StdRng, snapshots: &mut Snapshots) {
+    let centers: Vec<(i32, i32)> = rooms.iter().map(|room| room.center()).collect();
+    let mut joined = Vec::new();
+    match settings.layout {
+        CorridorLayout::Sequential => {
+            for i in 1..centers.len() {
+                joined.push((i - 1, i));
+            }
+        }
+        CorridorLayout::Nearest => {
+            for i in 1..centers.len() {
+                let nearest = (0..i).min_by_key(|&j| distance(centers[i], centers[j])).unwrap();
+                joined.push((nearest, i));
+            }
+        }
+        CorridorLayout::SpanningTree => {
+            // Prim's algorithm over the room centres
+            let mut in_tree = vec![false; centers.len()];
+            if !centers.is_empty() {
+                in_tree[0] = true;
+            }
+            for _ in 1..centers.len() {
+                let mut best: Option<(usize, usize, i32)> = None;
+                for a in (0..centers.len()).filter(|&a| in_tree[a]) {
+                    for b in (0..centers.len()).filter(|&b| !in_tree[b]) {
+                        let d = distance(centers[a], centers[b]);
+                        if best.map_or(true, |(_, _, best_distance)| d < best_distance) {
+                            best = Some((a, b, d));
+                        }
+                    }
+                }
+                let (a, b, _) = best.unwrap();
+                in_tree[b] = true;
+                joined.push((a, b));
+            }
+        }
+    }
+
+    for &(a, b) in joined.iter() {
+        dig(map, centers[a], centers[b], settings.shape, rng);
+        snapshots.take(map);
+    }
+    add_loops(map, rooms, &mut joined, settings, rng, snapshots);
+}
+
+/// Joins up to `settings.extra_loops` random rooms to their nearest room that they aren't
+/// already joined to. `joined` holds the pairs of room indices that have a corridor.
+pub fn add_loops(
+    map: &mut Map,
+    rooms: &[Rect],
+    joined: &mut Vec<(usize, usize)>,
+    settings: &CorridorSettings,
+    rng: &mut StdRng,
+    snapshots: &mut Snapshots,
+) {
+    if rooms.len() < 3 {
+        return;
+    }
+    let centers: Vec<(i32, i32)> = rooms.iter().map(|room| room.center()).collect();
+    for _ in 0..settings.extra_loops {
+        let a = rng.gen_range(0..centers.len());
+        let nearest = (0..centers.len())
+            .filter(|&b| b != a && !joined.contains(&(a, b)) && !joined.contains(&(b, a)))
+            .min_by_key(|&b| distance(centers[a], centers[b]));
+        if let Some(b) = nearest {
+            dig(map, centers[a], centers[b], settings.shape, rng);
+            joined.push((a, b));
+            snapshots.take(map);
+        }
+    }
+}
+
+/// Digs a corridor of the given shape from `from` to `to`.
+pub fn dig(map: &mut Map, from: (i32, i32), to: (i32, i32), shape: CorridorShape, rng: &mut StdRng) {
+    match shape {
+        CorridorShape::LShaped => {
+            if rng.gen() {
+                map.apply_horizontal_tunnel(from.0, to.0, from.1);
+                map.apply_vertical_tunnel(from.1, to.1, to.0);
+            } else {
+                map.apply_vertical_tunnel(from.1, to.1, from.0);
+                map.apply_horizontal_tunnel(from.0, to.0, to.1);
+            }
+        }
+        CorridorShape::Straight => dig_line(map, from, to),
+        CorridorShape::Drunken => dig_drunken(map, from, to, rng),
+    }
+}
+
+fn dig_line(map: &mut Map, (mut x, mut y): (i32, i32), to: (i32, i32)) {
+    let (dx, dy) = ((to.0 - x).abs(), -(to.1 - y).abs());
+    let (step_x, step_y) = ((to.0 - x).signum(), (to.1 - y).signum());
+    let mut error = dx + dy;
+    carve(map, x, y);
+    while (x, y) != to {
+        let doubled = error * 2;
+        let (moves_x, moves_y) = (doubled >= dy, doubled <= dx);
+        if moves_x {
+            error += dy;
+            x += step_x;
+        }
+        if moves_y {
+            // Dig the corner of a diagonal step too, as movement is only orthogonal
+            if moves_x {
+                carve(map, x, y);
+            }
+            error += dx;
+            y += step_y;
+        }
+        carve(map, x, y);
+    }
+}
+
+fn dig_drunken(map: &mut Map, (mut x, mut y): (i32, i32), to: (i32, i32), rng: &mut StdRng) {
+    let (min_x, max_x) = (-map.width / 2 + 1, map.width - map.width / 2 - 2);
+    let (min_y, max_y) = (-map.height / 2 + 1, map.height - map.height / 2 - 2);
+    // Give up wandering eventually and finish the corridor off straight
+    let mut steps_left = distance((x, y), to) * 4;
+
+    carve(map, x, y);
+    while (x, y) != to && steps_left > 0 {
+        let (step_x, step_y) = if rng.gen_bool(DRUNKEN_BIAS) {
+            if (to.0 - x).abs() > (to.1 - y).abs() {
+                ((to.0 - x).signum(), 0)
+            } else {
+                (0, (to.1 - y).signum())
+            }
+        } else {
+            *[(-1, 0), (1, 0), (0, -1), (0, 1)].choose(rng).unwrap()
+        };
+        x = (x + step_x).max(min_x).min(max_x);
+        y = (y + step_y).max(min_y).min(max_y);
+        carve(map, x, y);
+        steps_left -= 1;
+    }
+    if (x, y) != to {
+        dig_line(map, (x, y), to);
+    }
+}
+
+fn carve(map: &mut Map, x: i32, y: i32) {
+    if map.in_bounds(x, y) {
+        let idx = map.xy_idx(x, y);
+        map.tiles[idx] = TileType::Floor;
+    }
+}
+
+fn distance(a: (i32, i32), b: (i32, i32)) -> i32 {
+    (a.0 - b.0).abs() + (a.1 - b.1).abs()
+}
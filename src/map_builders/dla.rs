@@ -0,0 +1,92 @@
+use rand::prelude::*;
+use serde::Deserialize;
+
+use super::common::{
+    floor_closest_to_center, floor_count, keep_largest_region, paint, random_step, spawn_points_by_area, Snapshots,
+    Symmetry,
+};
+use super::{MapBuild, MapBuilder};
+use crate::map::{Map, TileType};
+
+/// Walkers released between snapshots, as there are far too many to record each one.
+const WALKERS_PER_SNAPSHOT: i32 = 100;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct DlaSettings {
+    /// Fraction of the map to open up, from 0.0 to 1.0.
+    pub floor_percent: f32,
+    /// Steps a walker takes looking for the aggregate before it is abandoned.
+    pub walker_lifetime: i32,
+    pub max_walkers: i32,
+    pub symmetry: Symmetry,
+    pub brush_size: i32,
+}
+
+/// Diffusion-limited aggregation: walkers wander in from random points and stick to the
+/// floor they bump into, growing branching caves outward from a central seed.
+pub struct DlaBuilder {
+    width: i32,
+    height: i32,
+    settings: DlaSettings,
+}
+
+impl DlaBuilder {
+    pub fn new(width: i32, height: i32, settings: DlaSettings) -> Self {
+        Self {
+            width,
+            height,
+            settings,
+        }
+    }
+}
+
+impl MapBuilder for DlaBuilder {
+    fn build(&mut self, rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild {
+        let mut map = Map::new(self.width, self.height);
+        let settings = &self.settings;
+        let desired_floor = (map.tiles.len() as f32 * settings.floor_percent) as usize;
+
+        // Central seed
+        let (cx, cy) = (map.width / 2, map.height / 2);
+        for (dx, dy) in [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+            map.tiles[((cy + dy) * map.width + cx + dx) as usize] = TileType::Floor;
+        }
+        let mut floor = floor_count(&map);
+
+        let mut walkers = 0;
+        while floor < desired_floor && walkers < settings.max_walkers {
+            let mut ax = rng.gen_range(1..map.width - 1);
+            let mut ay = rng.gen_range(1..map.height - 1);
+            let (mut prev_x, mut prev_y) = (ax, ay);
+
+            let mut steps = 0;
+            while map.tiles[(ay * map.width + ax) as usize] == TileType::Wall && steps < settings.walker_lifetime {
+                prev_x = ax;
+                prev_y = ay;
+                random_step(&map, rng, &mut ax, &mut ay);
+                steps += 1;
+            }
+
+            // Stick to the aggregate just before the floor it reached
+            if steps > 0 && map.tiles[(ay * map.width + ax) as usize] != TileType::Wall {
+                floor += paint(&mut map, settings.symmetry, settings.brush_size, prev_x, prev_y);
+            }
+            walkers += 1;
+            if walkers % WALKERS_PER_SNAPSHOT == 0 {
+                snapshots.take(&map);
+            }
+        }
+
+        keep_largest_region(&mut map);
+
+        let player_start = floor_closest_to_center(&map);
+        let spawn_points = spawn_points_by_area(&map, rng, player_start);
+
+        MapBuild {
+            map,
+            player_start,
+            spawn_points,
+            named_spawns: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use rand::prelude::*;
+use serde::Deserialize;
+
+use super::common::{
+    floor_closest_to_center, keep_largest_region, paint, random_step, spawn_points_by_area, Snapshots, Symmetry,
+};
+use super::{MapBuild, MapBuilder};
+use crate::map::{Map, TileType};
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct DrunkardSettings {
+    /// Fraction of the map to open up, from 0.0 to 1.0.
+    pub floor_percent: f32,
+    pub walker_lifetime: i32,
+    pub max_walkers: i32,
+    pub symmetry: Symmetry,
+    pub brush_size: i32,
+}
+
+/// Sends out random walkers that carve floor wherever they stumble.
+pub struct DrunkardsWalkBuilder {
+    width: i32,
+    height: i32,
+    settings: DrunkardSettings,
+}
+
+impl DrunkardsWalkBuilder {
+    pub fn new(width: i32, height: i32, settings: DrunkardSettings) -> Self {
+        Self {
+            width,
+            height,
+            settings,
+        }
+    }
+}
+
+impl MapBuilder for DrunkardsWalkBuilder {
+    fn build(&mut self, rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild {
+        let mut map = Map::new(self.width, self.height);
+        let settings = &self.settings;
+        let start = (map.width / 2, map.height / 2);
+        let desired_floor = (map.tiles.len() as f32 * settings.floor_percent) as usize;
+
+        let mut floor = paint(&mut map, settings.symmetry, settings.brush_size, start.0, start.1);
+
+        let mut walkers = 0;
+        while floor < desired_floor && walkers < settings.max_walkers {
+            // Later walkers set off from somewhere already dug so the caves stay joined
+            let (mut ax, mut ay) = if walkers == 0 {
+                start
+            } else {
+                let open: Vec<usize> = map
+                    .tiles
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, tile)| **tile == TileType::Floor)
+                    .map(|(idx, _)| idx)
+                    .collect();
+                let idx = *open.choose(rng).expect("Map has no floor") as i32;
+                (idx % map.width, idx / map.width)
+            };
+
+            for _ in 0..settings.walker_lifetime {
+                floor += paint(&mut map, settings.symmetry, settings.brush_size, ax, ay);
+                random_step(&map, rng, &mut ax, &mut ay);
+            }
+            snapshots.take(&map);
+            walkers += 1;
+        }
+
+        keep_largest_region(&mut map);
+
+        let player_start = floor_closest_to_center(&map);
+        let spawn_points = spawn_points_by_area(&map, rng, player_start);
+
+        MapBuild {
+            map,
+            player_start,
+            spawn_points,
+            named_spawns: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use bevy::log::{info, warn};
+use rand::rngs::StdRng;
+use serde::Deserialize;
+
+use crate::config::MapGenConfig;
+use crate::map::{Map, TileType};
+
+mod ascii;
+mod bsp_dungeon;
+mod cellular_automata;
+mod common;
+mod corridors;
+mod dla;
+mod drunkard;
+mod prefab;
+mod room_themes;
+mod simple_map;
+mod wfc;
+
+pub use ascii::{to_ascii, AsciiMapBuilder};
+pub use bsp_dungeon::BspDungeonBuilder;
+pub use common::Snapshots;
+pub use corridors::CorridorSettings;
+pub use cellular_automata::CellularAutomataBuilder;
+pub use dla::{DlaBuilder, DlaSettings};
+pub use drunkard::{DrunkardSettings, DrunkardsWalkBuilder};
+pub use prefab::{load_prefabs, Prefab, PrefabLevelBuilder, PrefabLibrary, PrefabVaultBuilder};
+pub use simple_map::SimpleMapBuilder;
+pub use wfc::{WaveFunctionCollapseBuilder, WfcSettings};
+
+const MAX_BUILD_ATTEMPTS: i32 = 10;
+
+/// Everything a builder produces: the terrain and where things should be placed on it.
+pub struct MapBuild {
+    pub map: Map,
+    pub player_start: (i32, i32),
+    /// Points for the random spawn table.
+    pub spawn_points: Vec<(i32, i32)>,
+    /// Specific things asked for by name, e.g. by a prefab's legend.
+    pub named_spawns: Vec<((i32, i32), String)>,
+}
+
+/// A map generation algorithm. Builders only deal with plain data so they can be run
+/// without a window; `map::build_map` turns the result into tiles and entities.
+pub trait MapBuilder {
+    /// Builds a level, handing `snapshots` the map after each step worth watching.
+    fn build(&mut self, rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild;
+}
+
+/// Which layout algorithm to use, chosen in `assets/config/mapgen.ron`.
+#[derive(Deserialize, Clone, Debug)]
+pub enum BuilderType {
+    Simple,
+    Bsp,
+    CellularAutomata,
+    DrunkardsWalk,
+    Dla,
+    WaveFunctionCollapse,
+    /// Use the named prefab as the whole level.
+    PrefabLevel(String),
+    /// Load `assets/maps/<name>.txt` as every level, skipping generation entirely.
+    AsciiMap(String),
+}
+
+/// Generates the level at `depth` with the configured builder, then decorates it, themes its
+/// rooms and adds its doors and stairs.
+///
+/// Every level is checked to be fully reachable from the player start. Generated levels with
+/// fewer than `min_open_tiles` reachable tiles are thrown away and rebuilt, and if none of the
+/// `MAX_BUILD_ATTEMPTS` attempts is big enough, the largest one is kept. Prefab levels are kept
+/// whatever their size, as rebuilding them gives the same layout.
+pub fn build_level(
+    config: &MapGenConfig,
+    prefabs: &[Prefab],
+    depth: i32,
+    rng: &mut StdRng,
+    snapshots: &mut Snapshots,
+) -> MapBuild {
+    if let BuilderType::AsciiMap(_) = config.builder {
+        // Loaded levels are played exactly as written
+        return select_builder(config, prefabs, depth).build(rng, snapshots);
+    }
+
+    let mut build = if let BuilderType::PrefabLevel(_) = config.builder {
+        let mut build = select_builder(config, prefabs, depth).build(rng, snapshots);
+        let stats = common::cull_unreachable(&mut build);
+        snapshots.take(&build.map);
+        info!(
+            "Built depth {} from a prefab: {} open tiles, longest path {}",
+            depth, stats.open_tiles, stats.longest_path
+        );
+        build
+    } else {
+        build_big_enough(config, prefabs, depth, rng, snapshots)
+    };
+    common::place_doors(&mut build.map);
+    snapshots.take(&build.map);
+    room_themes::apply_room_themes(&mut build, depth, rng);
+    snapshots.take(&build.map);
+    common::scatter_terrain(&mut build, rng);
+    snapshots.take(&build.map);
+    common::scatter_items(&mut build, rng);
+    common::place_stairs(&mut build, depth);
+    snapshots.take(&build.map);
+    build
+}
+
+/// Builds the level at `depth` until it has at least `min_open_tiles` reachable tiles, keeping
+/// the largest attempt if none do.
+fn build_big_enough(
+    config: &MapGenConfig,
+    prefabs: &[Prefab],
+    depth: i32,
+    rng: &mut StdRng,
+    snapshots: &mut Snapshots,
+) -> MapBuild {
+    let mut attempts = 0;
+    let mut largest: Option<(MapBuild, usize, Vec<Vec<TileType>>)> = None;
+    loop {
+        attempts += 1;
+        // Only the attempt that is kept gets played back
+        snapshots.frames.clear();
+        let mut build = select_builder(config, prefabs, depth).build(rng, snapshots);
+        let stats = common::cull_unreachable(&mut build);
+        snapshots.take(&build.map);
+
+        if stats.open_tiles >= config.min_open_tiles {
+            info!(
+                "Built depth {} in {} attempt(s): {} open tiles, longest path {}",
+                depth, attempts, stats.open_tiles, stats.longest_path
+            );
+            break build;
+        }
+        if largest.as_ref().map_or(true, |(_, open_tiles, _)| stats.open_tiles > *open_tiles) {
+            largest = Some((build, stats.open_tiles, std::mem::take(&mut snapshots.frames)));
+        }
+        if attempts >= MAX_BUILD_ATTEMPTS {
+            let (build, open_tiles, frames) = largest.unwrap();
+            warn!(
+                "Giving up on a bigger level at depth {}, keeping the largest with {} open tiles",
+                depth, open_tiles
+            );
+            snapshots.frames = frames;
+            break build;
+        }
+        warn!("Level only has {} open tiles, rebuilding", stats.open_tiles);
+    }
+}
+
+/// Picks the configured builder for a level at `depth`, adding prefab vaults to generated layouts.
+pub fn select_builder(config: &MapGenConfig, prefabs: &[Prefab], depth: i32) -> Box<dyn MapBuilder> {
+    let (width, height) = (config.width, config.height);
+    let builder: Box<dyn MapBuilder> = match &config.builder {
+        BuilderType::Simple => Box::new(SimpleMapBuilder::new(width, height, config.corridors.clone())),
+        BuilderType::Bsp => Box::new(BspDungeonBuilder::new(width, height, config.corridors.clone())),
+        BuilderType::CellularAutomata => Box::new(CellularAutomataBuilder::new(width, height)),
+        BuilderType::DrunkardsWalk => Box::new(DrunkardsWalkBuilder::new(width, height, config.drunkard.clone())),
+        BuilderType::Dla => Box::new(DlaBuilder::new(width, height, config.dla.clone())),
+        BuilderType::WaveFunctionCollapse => {
+            let sample = std::fs::read_to_string(&config.wfc.sample).expect("Unable to read WFC sample");
+            Box::new(WaveFunctionCollapseBuilder::new(width, height, &config.wfc, &sample))
+        }
+        BuilderType::PrefabLevel(name) => {
+            let prefab = prefabs
+                .iter()
+                .find(|prefab| &prefab.name == name)
+                .unwrap_or_else(|| panic!("No prefab named {}", name));
+            return Box::new(PrefabLevelBuilder::new(width, height, prefab.clone()));
+        }
+        BuilderType::AsciiMap(name) => return Box::new(AsciiMapBuilder::new(name)),
+    };
+
+    Box::new(PrefabVaultBuilder::new(builder, prefabs, depth, config.max_vaults))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::MapSeed;
+
+    #[test]
+    fn same_seed_builds_the_same_level() {
+        let config: MapGenConfig =
+            ron::from_str(&std::fs::read_to_string("assets/config/mapgen.ron").unwrap()).unwrap();
+        let prefabs = load_prefabs("assets/prefabs");
+        let seed = MapSeed(42);
+        for depth in 1..=3 {
+            let first = build_level(&config, &prefabs.0, depth, &mut seed.level_rng(depth), &mut Snapshots::new(false));
+            let second = build_level(&config, &prefabs.0, depth, &mut seed.level_rng(depth), &mut Snapshots::new(false));
+            assert_eq!(first.map.tiles, second.map.tiles);
+            assert_eq!(first.map.rooms, second.map.rooms);
+            assert_eq!(first.player_start, second.player_start);
+            assert_eq!(first.spawn_points, second.spawn_points);
+            assert_eq!(first.named_spawns, second.named_spawns);
+        }
+    }
+}
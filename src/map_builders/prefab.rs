@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use rand::prelude::*;
+use serde::Deserialize;
+
+use super::common::{floor_closest_to_center, Snapshots};
+use super::{MapBuild, MapBuilder};
+use crate::map::{Map, TileType};
+
+const PLACEMENT_ATTEMPTS: i32 = 50;
+/// Spawn name in a legend that marks where the player starts on a prefab level.
+const PLAYER_SPAWN: &str = "Player";
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LegendEntry {
+    pub tile: TileType,
+    pub spawn: Option<String>,
+}
+
+/// A hand-authored room or level loaded from `assets/prefabs`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Prefab {
+    pub name: String,
+    /// Rows of the template, top row first.
+    pub template: Vec<String>,
+    pub legend: HashMap<char, LegendEntry>,
+    pub min_depth: i32,
+    pub max_depth: i32,
+    /// Relative chance of being picked among the prefabs allowed at a depth.
+    pub rarity: u32,
+}
+
+impl Prefab {
+    pub fn size(&self) -> (i32, i32) {
+        let width = self.template.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+        (width as i32, self.template.len() as i32)
+    }
+
+    /// Writes the template with its bottom left corner at the array coordinates
+    /// `(left, bottom)` and returns what it asks to be spawned.
+    fn stamp(&self, map: &mut Map, left: i32, bottom: i32) -> Vec<((i32, i32), String)> {
+        let (_, height) = self.size();
+        let mut spawns = Vec::new();
+        for (row, line) in self.template.iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let entry = match self.legend.get(&c) {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                let ax = left + column as i32;
+                let ay = bottom + height - 1 - row as i32;
+                let idx = (ay * map.width + ax) as usize;
+                map.tiles[idx] = entry.tile;
+                if let Some(spawn) = &entry.spawn {
+                    spawns.push((map.idx_xy(idx), spawn.clone()));
+                }
+            }
+        }
+        spawns
+    }
+}
+
+/// Every prefab available to the builders.
+pub struct PrefabLibrary(pub Vec<Prefab>);
+
+/// Reads every `.ron` prefab in `dir`, sorted by file name so generation stays reproducible.
+pub fn load_prefabs(dir: &str) -> PrefabLibrary {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .expect("Unable to read prefab directory")
+        .map(|entry| entry.expect("Unable to read prefab").path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "ron"))
+        .collect();
+    paths.sort();
+
+    PrefabLibrary(
+        paths
+            .iter()
+            .map(|path| {
+                ron::from_str(&std::fs::read_to_string(path).unwrap())
+                    .unwrap_or_else(|e| panic!("Invalid prefab {}: {}", path.display(), e))
+            })
+            .collect(),
+    )
+}
+
+/// Runs another builder, then sets vaults into solid rock and tunnels each one to the nearest floor.
+pub struct PrefabVaultBuilder {
+    inner: Box<dyn MapBuilder>,
+    vaults: Vec<Prefab>,
+    max_vaults: i32,
+}
+
+impl PrefabVaultBuilder {
+    /// Only the prefabs allowed at `depth` are considered.
+    pub fn new(inner: Box<dyn MapBuilder>, prefabs: &[Prefab], depth: i32, max_vaults: i32) -> Self {
+        Self {
+            inner,
+            vaults: prefabs
+                .iter()
+                .filter(|prefab| prefab.min_depth <= depth && depth <= prefab.max_depth)
+                .cloned()
+                .collect(),
+            max_vaults,
+        }
+    }
+}
+
+impl MapBuilder for PrefabVaultBuilder {
+    fn build(&mut self, rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild {
+        let mut build = self.inner.build(rng, snapshots);
+
+        for _ in 0..self.max_vaults {
+            let vault = match self.vaults.choose_weighted(rng, |prefab| prefab.rarity) {
+                Ok(vault) => vault,
+                Err(_) => break,
+            };
+            if let Some(spawns) = place_vault(&mut build.map, vault, rng) {
+                build.named_spawns.extend(spawns);
+                snapshots.take(&build.map);
+            }
+        }
+
+        build
+    }
+}
+
+fn place_vault(map: &mut Map, vault: &Prefab, rng: &mut StdRng) -> Option<Vec<((i32, i32), String)>> {
+    let (width, height) = vault.size();
+    if width + 3 > map.width || height + 3 > map.height {
+        return None;
+    }
+
+    for _ in 0..PLACEMENT_ATTEMPTS {
+        let left = rng.gen_range(1..map.width - width - 1);
+        let bottom = rng.gen_range(1..map.height - height - 1);
+
+        // Only carve into solid rock, with a margin, so rooms and corridors are never cut
+        let fits = (bottom - 1..=bottom + height)
+            .all(|ay| (left - 1..=left + width).all(|ax| map.tiles[(ay * map.width + ax) as usize] == TileType::Wall));
+        if !fits {
+            continue;
+        }
+
+        let outside: Vec<usize> = (0..map.tiles.len())
+            .filter(|&idx| map.tiles[idx] != TileType::Wall)
+            .collect();
+        let spawns = vault.stamp(map, left, bottom);
+        let inside: Vec<usize> = (0..map.tiles.len())
+            .filter(|&idx| {
+                let (ax, ay) = (idx as i32 % map.width, idx as i32 / map.width);
+                ax >= left && ax < left + width && ay >= bottom && ay < bottom + height && map.tiles[idx] != TileType::Wall
+            })
+            .collect();
+        connect_closest(map, &inside, &outside);
+
+        return Some(spawns);
+    }
+
+    None
+}
+
+/// Tunnels between the closest pair of tiles taken from `from` and `to`.
+fn connect_closest(map: &mut Map, from: &[usize], to: &[usize]) {
+    let mut best: Option<((i32, i32), (i32, i32), i32)> = None;
+    for &a in from.iter() {
+        for &b in to.iter() {
+            let (ax, ay) = map.idx_xy(a);
+            let (bx, by) = map.idx_xy(b);
+            let distance = (ax - bx).abs() + (ay - by).abs();
+            if best.map_or(true, |(_, _, best_distance)| distance < best_distance) {
+                best = Some(((ax, ay), (bx, by), distance));
+            }
+        }
+    }
+
+    if let Some(((ax, ay), (bx, by), _)) = best {
+        map.apply_horizontal_tunnel(ax, bx, ay);
+        map.apply_vertical_tunnel(ay, by, bx);
+    }
+}
+
+/// Uses a single prefab, centred on the map, as the whole level.
+pub struct PrefabLevelBuilder {
+    width: i32,
+    height: i32,
+    prefab: Prefab,
+}
+
+impl PrefabLevelBuilder {
+    pub fn new(width: i32, height: i32, prefab: Prefab) -> Self {
+        Self { width, height, prefab }
+    }
+}
+
+impl MapBuilder for PrefabLevelBuilder {
+    fn build(&mut self, _rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild {
+        let mut map = Map::new(self.width, self.height);
+        let (width, height) = self.prefab.size();
+        let (left, bottom) = ((map.width - width) / 2, (map.height - height) / 2);
+        let spawns = self.prefab.stamp(&mut map, left, bottom);
+        snapshots.take(&map);
+
+        let player_start = spawns
+            .iter()
+            .find(|(_, name)| name == PLAYER_SPAWN)
+            .map(|(point, _)| *point)
+            .unwrap_or_else(|| floor_closest_to_center(&map));
+        let named_spawns = spawns.into_iter().filter(|(_, name)| name != PLAYER_SPAWN).collect();
+
+        MapBuild {
+            map,
+            player_start,
+            spawn_points: Vec::new(),
+            named_spawns,
+        }
+    }
+}
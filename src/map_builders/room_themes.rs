@@ -0,0 +1,176 @@
+use rand::prelude::*;
+
+use super::MapBuild;
+use crate::map::{Map, RoomInfo, RoomTheme, TileType};
+use crate::rect::Rect;
+
+const THEMES: [RoomTheme; 6] = [
+    RoomTheme::Plain,
+    RoomTheme::Library,
+    RoomTheme::Barracks,
+    RoomTheme::Treasury,
+    RoomTheme::Shrine,
+    RoomTheme::Flooded,
+];
+
+/// How likely each theme is at `depth`, relative to the others.
+fn theme_weight(theme: RoomTheme, depth: i32) -> i32 {
+    match theme {
+        RoomTheme::Plain => 12,
+        RoomTheme::Library => 3,
+        RoomTheme::Barracks => 2 + depth,
+        RoomTheme::Treasury => (depth - 2).max(0),
+        RoomTheme::Shrine => 2,
+        RoomTheme::Flooded => {
+            if depth >= 2 {
+                3
+            } else {
+                0
+            }
+        }
+    }
+}
+
+/// What can be spawned in a room of each theme, by relative weight, and how many spawns to roll.
+fn spawn_table(theme: RoomTheme) -> (&'static [(&'static str, u32)], i32, i32) {
+    match theme {
+        RoomTheme::Plain => (&[], 0, 0),
+        RoomTheme::Library => (
+            &[
+                ("Goblin", 2),
+                ("Magic Missile Scroll", 3),
+                ("Fireball Scroll", 1),
+                ("Confusion Scroll", 2),
+            ],
+            1,
+            2,
+        ),
+        RoomTheme::Barracks => (&[("Goblin", 6), ("Orc", 2), ("Dagger", 1), ("Shield", 1)], 2, 4),
+        RoomTheme::Treasury => (&[("Orc", 2), ("Health Potion", 4), ("Dagger", 1), ("Shield", 1)], 2, 3),
+        RoomTheme::Shrine => (&[("Health Potion", 1)], 0, 1),
+        RoomTheme::Flooded => (&[("Goblin", 1)], 0, 1),
+    }
+}
+
+/// Gives every room but the first a theme, furnishes it and replaces its spawn with ones from
+/// the theme's table. The player starts in the first room, so it is always left plain.
+pub fn apply_room_themes(build: &mut MapBuild, depth: i32, rng: &mut StdRng) {
+    let rooms = build.map.rooms.clone();
+    build.map.room_info = vec![
+        RoomInfo {
+            theme: RoomTheme::Plain,
+            entered: false,
+        };
+        rooms.len()
+    ];
+
+    for (i, room) in rooms.iter().enumerate().skip(1) {
+        // Every theme needs space for furniture around a free middle
+        if room.x2 - room.x1 < 4 || room.y2 - room.y1 < 4 {
+            continue;
+        }
+        let theme = *THEMES
+            .choose_weighted(rng, |theme| theme_weight(*theme, depth))
+            .unwrap();
+        if theme == RoomTheme::Plain {
+            continue;
+        }
+        build.map.room_info[i].theme = theme;
+
+        furnish(&mut build.map, room, theme);
+
+        build.spawn_points.retain(|&(x, y)| !room.contains(x, y));
+        let (table, min_spawns, max_spawns) = spawn_table(theme);
+        if table.is_empty() {
+            continue;
+        }
+        let mut free: Vec<(i32, i32)> = (room.y1 + 1..=room.y2)
+            .flat_map(|y| (room.x1 + 1..=room.x2).map(move |x| (x, y)))
+            .filter(|&(x, y)| {
+                matches!(
+                    build.map.get(x, y),
+                    Some(TileType::Floor) | Some(TileType::ShallowWater) | Some(TileType::Bed)
+                )
+            })
+            .collect();
+        free.shuffle(rng);
+        for _ in 0..rng.gen_range(min_spawns..=max_spawns) {
+            let point = match free.pop() {
+                Some(point) => point,
+                None => break,
+            };
+            let (name, _) = table.choose_weighted(rng, |(_, weight)| *weight).unwrap();
+            build.named_spawns.push((point, name.to_string()));
+        }
+    }
+}
+
+fn furnish(map: &mut Map, room: &Rect, theme: RoomTheme) {
+    let center = room.center();
+    let corners = [
+        (room.x1 + 1, room.y1 + 1),
+        (room.x2, room.y1 + 1),
+        (room.x1 + 1, room.y2),
+        (room.x2, room.y2),
+    ];
+    match theme {
+        RoomTheme::Plain => {}
+        RoomTheme::Library => {
+            for (x, y) in shelf_tiles(room) {
+                place(map, room, x, y, TileType::Bookshelf);
+            }
+        }
+        RoomTheme::Barracks => {
+            for y in (room.y1 + 1..=room.y2).step_by(2) {
+                place(map, room, room.x1 + 1, y, TileType::Bed);
+                place(map, room, room.x2, y, TileType::Bed);
+            }
+        }
+        RoomTheme::Treasury => {
+            for &(x, y) in corners.iter() {
+                place(map, room, x, y, TileType::Pillar);
+            }
+        }
+        RoomTheme::Shrine => {
+            place(map, room, center.0, center.1, TileType::Altar);
+            for &(x, y) in corners.iter() {
+                place(map, room, x, y, TileType::Pillar);
+            }
+        }
+        RoomTheme::Flooded => {
+            place(map, room, center.0, center.1, TileType::DeepWater);
+            for y in room.y1 + 1..=room.y2 {
+                for x in room.x1 + 1..=room.x2 {
+                    place(map, room, x, y, TileType::ShallowWater);
+                }
+            }
+        }
+    }
+}
+
+/// The ring of tiles just inside the room's walls, leaving out each corner and the tiles beside
+/// it. A corner left free by a nearby entrance could otherwise be shelved in.
+fn shelf_tiles(room: &Rect) -> Vec<(i32, i32)> {
+    let (left, right, bottom, top) = (room.x1 + 1, room.x2, room.y1 + 1, room.y2);
+    let near_corner = |x: i32, y: i32| (x - left).min(right - x) <= 1 && (y - bottom).min(top - y) <= 1;
+    (bottom..=top)
+        .flat_map(|y| (left..=right).map(move |x| (x, y)))
+        .filter(|&(x, y)| (x == left || x == right || y == bottom || y == top) && !near_corner(x, y))
+        .collect()
+}
+
+/// Replaces floor at `(x, y)` with furniture. Tiles next to a way into `room` are left alone,
+/// so nothing blocks a door or corridor.
+fn place(map: &mut Map, room: &Rect, x: i32, y: i32, tile: TileType) {
+    let idx = map.xy_idx(x, y);
+    if map.tiles[idx] != TileType::Floor {
+        return;
+    }
+    let by_entrance = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+        let (nx, ny) = (x + dx, y + dy);
+        !room.contains(nx, ny) && map.get(nx, ny).map_or(false, |neighbour| neighbour != TileType::Wall)
+    });
+    if !by_entrance {
+        map.tiles[idx] = tile;
+    }
+}
@@ -0,0 +1,89 @@
+use rand::prelude::*;
+
+use super::common::Snapshots;
+use super::corridors::{connect_rooms, CorridorSettings};
+use super::{MapBuild, MapBuilder};
+use crate::map::Map;
+use crate::rect::Rect;
+
+const MAX_ROOMS: i32 = 30;
+const MIN_SIZE: i32 = 6;
+const MAX_SIZE: i32 = 10;
+
+/// Scatters non-overlapping rooms and joins them with corridors.
+pub struct SimpleMapBuilder {
+    width: i32,
+    height: i32,
+    corridors: CorridorSettings,
+}
+
+impl SimpleMapBuilder {
+    pub fn new(width: i32, height: i32, corridors: CorridorSettings) -> Self {
+        Self {
+            width,
+            height,
+            corridors,
+        }
+    }
+}
+
+impl MapBuilder for SimpleMapBuilder {
+    fn build(&mut self, rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild {
+        let mut map = Map::new(self.width, self.height);
+        let mut rooms: Vec<Rect> = Vec::new();
+
+        for _ in 0..MAX_ROOMS {
+            let w = rng.gen_range(MIN_SIZE..=MAX_SIZE);
+            let h = rng.gen_range(MIN_SIZE..=MAX_SIZE);
+            let x = rng.gen_range((-map.width / 2)..(map.width / 2 - w - 1));
+            let y = rng.gen_range((-map.height / 2)..(map.height / 2 - h - 1));
+            let new_room = Rect::new(x, y, w, h);
+            let mut ok = true;
+            for other_room in rooms.iter() {
+                if new_room.intersect(other_room) {
+                    ok = false
+                }
+            }
+            if ok {
+                map.apply_room(&new_room);
+                snapshots.take(&map);
+                rooms.push(new_room);
+            }
+        }
+
+        connect_rooms(&mut map, &rooms, &self.corridors, rng, snapshots);
+
+        let player_start = rooms[0].center();
+        let spawn_points = rooms.iter().skip(1).map(|room| room.center()).collect();
+        map.rooms = rooms;
+
+        MapBuild {
+            map,
+            player_start,
+            spawn_points,
+            named_spawns: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileType;
+    use crate::map_builders::corridors::{CorridorLayout, CorridorShape};
+
+    #[test]
+    fn player_starts_on_floor() {
+        let corridors = CorridorSettings {
+            layout: CorridorLayout::SpanningTree,
+            shape: CorridorShape::LShaped,
+            extra_loops: 4,
+        };
+        for seed in 0..10 {
+            let build = SimpleMapBuilder::new(80, 50, corridors.clone())
+                .build(&mut StdRng::seed_from_u64(seed), &mut Snapshots::new(false));
+            let (x, y) = build.player_start;
+            assert_eq!(build.map.get(x, y), Some(TileType::Floor));
+        }
+    }
+}
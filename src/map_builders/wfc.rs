@@ -0,0 +1,242 @@
+use bevy::log::warn;
+use rand::prelude::*;
+use serde::Deserialize;
+
+use super::common::{floor_closest_to_center, floor_count, keep_largest_region, spawn_points_by_area, Snapshots};
+use super::{CellularAutomataBuilder, MapBuild, MapBuilder};
+use crate::map::{Map, TileType};
+
+/// Offsets to the north, south, east and west neighbours of an output cell.
+const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (0, 1), (1, 0), (-1, 0)];
+/// Levels opening up less than this fraction of the map are rejected.
+const MIN_FLOOR_FRACTION: f32 = 0.2;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct WfcSettings {
+    /// ASCII sample to imitate: `#` is wall and `.` is floor.
+    pub sample: String,
+    pub chunk_size: usize,
+    pub max_retries: i32,
+}
+
+struct Pattern {
+    /// `chunk_size` rows of `chunk_size` tiles, top row first.
+    tiles: Vec<TileType>,
+    weight: u32,
+}
+
+/// Wave Function Collapse over square chunks cut from a hand-drawn sample. Two chunks may sit
+/// side by side when their touching edges are identical. Falls back to cellular automata
+/// caves if the sample can't be solved.
+pub struct WaveFunctionCollapseBuilder {
+    width: i32,
+    height: i32,
+    chunk_size: usize,
+    max_retries: i32,
+    patterns: Vec<Pattern>,
+    /// `compatible[direction][a][b]` is true if `b` may be placed in `direction` of `a`.
+    compatible: Vec<Vec<Vec<bool>>>,
+}
+
+impl WaveFunctionCollapseBuilder {
+    pub fn new(width: i32, height: i32, settings: &WfcSettings, sample: &str) -> Self {
+        let patterns = extract_patterns(sample, settings.chunk_size);
+        let compatible = (0..DIRECTIONS.len())
+            .map(|direction| {
+                patterns
+                    .iter()
+                    .map(|a| {
+                        patterns
+                            .iter()
+                            .map(|b| {
+                                edge(&a.tiles, settings.chunk_size, direction)
+                                    == edge(&b.tiles, settings.chunk_size, opposite(direction))
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            chunk_size: settings.chunk_size,
+            max_retries: settings.max_retries,
+            patterns,
+            compatible,
+        }
+    }
+
+    /// Collapses a `columns` x `rows` grid of chunks, or returns `None` on a contradiction.
+    fn solve(&self, columns: usize, rows: usize, rng: &mut StdRng) -> Option<Vec<usize>> {
+        let mut cells: Vec<Vec<usize>> = vec![(0..self.patterns.len()).collect(); columns * rows];
+
+        loop {
+            // Collapse the most constrained cell first
+            let mut lowest: Option<(usize, usize)> = None;
+            for (idx, options) in cells.iter().enumerate() {
+                match options.len() {
+                    0 => return None,
+                    1 => {}
+                    count => {
+                        if lowest.map_or(true, |(_, lowest_count)| count < lowest_count) {
+                            lowest = Some((idx, count));
+                        }
+                    }
+                }
+            }
+            let idx = match lowest {
+                Some((idx, _)) => idx,
+                None => break,
+            };
+
+            let choice = *cells[idx]
+                .choose_weighted(rng, |&pattern| self.patterns[pattern].weight)
+                .ok()?;
+            cells[idx] = vec![choice];
+
+            if !self.propagate(&mut cells, idx, columns, rows) {
+                return None;
+            }
+        }
+
+        Some(cells.iter().map(|options| options[0]).collect())
+    }
+
+    /// Removes options that no longer fit next to their neighbours, starting from `start`.
+    fn propagate(&self, cells: &mut Vec<Vec<usize>>, start: usize, columns: usize, rows: usize) -> bool {
+        let mut stack = vec![start];
+        while let Some(idx) = stack.pop() {
+            let x = (idx % columns) as i32;
+            let y = (idx / columns) as i32;
+            for (direction, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= columns as i32 || ny >= rows as i32 {
+                    continue;
+                }
+                let neighbour = ny as usize * columns + nx as usize;
+                let options = cells[idx].clone();
+                let before = cells[neighbour].len();
+                cells[neighbour].retain(|&b| options.iter().any(|&a| self.compatible[direction][a][b]));
+
+                if cells[neighbour].is_empty() {
+                    return false;
+                }
+                if cells[neighbour].len() != before {
+                    stack.push(neighbour);
+                }
+            }
+        }
+        true
+    }
+
+    fn try_build(&self, rng: &mut StdRng) -> Option<Map> {
+        let mut map = Map::new(self.width, self.height);
+        let n = self.chunk_size;
+        // Leave the outer edge solid
+        let columns = (map.width as usize - 2) / n;
+        let rows = (map.height as usize - 2) / n;
+        let solution = self.solve(columns, rows, rng)?;
+
+        for (cell, &pattern) in solution.iter().enumerate() {
+            let (column, row) = (cell % columns, cell / columns);
+            for (i, tile) in self.patterns[pattern].tiles.iter().enumerate() {
+                let x = 1 + column * n + i % n;
+                // Patterns are stored top row first, the map grows upward
+                let y = map.height as usize - 2 - (row * n + i / n);
+                map.tiles[y * map.width as usize + x] = *tile;
+            }
+        }
+
+        keep_largest_region(&mut map);
+        if (floor_count(&map) as f32) < map.tiles.len() as f32 * MIN_FLOOR_FRACTION {
+            return None;
+        }
+        Some(map)
+    }
+}
+
+impl MapBuilder for WaveFunctionCollapseBuilder {
+    fn build(&mut self, rng: &mut StdRng, snapshots: &mut Snapshots) -> MapBuild {
+        if !self.patterns.is_empty() {
+            for _ in 0..self.max_retries {
+                if let Some(map) = self.try_build(rng) {
+                    snapshots.take(&map);
+                    let player_start = floor_closest_to_center(&map);
+                    let spawn_points = spawn_points_by_area(&map, rng, player_start);
+                    return MapBuild {
+                        map,
+                        player_start,
+                        spawn_points,
+                        named_spawns: Vec::new(),
+                    };
+                }
+            }
+        }
+
+        warn!("Wave function collapse failed, falling back to cellular automata");
+        CellularAutomataBuilder::new(self.width, self.height).build(rng, snapshots)
+    }
+}
+
+/// Cuts the sample into `n` x `n` chunks, adding their mirror images for variety and counting
+/// how often each distinct chunk appears.
+fn extract_patterns(sample: &str, n: usize) -> Vec<Pattern> {
+    let grid: Vec<Vec<TileType>> = sample
+        .lines()
+        .map(|line| {
+            line.trim_end()
+                .chars()
+                .map(|c| if c == '.' { TileType::Floor } else { TileType::Wall })
+                .collect()
+        })
+        .collect();
+    let height = grid.len();
+    let width = grid.iter().map(|row| row.len()).max().unwrap_or(0);
+    let at = |x: usize, y: usize| *grid[y].get(x).unwrap_or(&TileType::Wall);
+
+    let mut patterns: Vec<Pattern> = Vec::new();
+    if n == 0 {
+        return patterns;
+    }
+    for chunk_y in 0..height / n {
+        for chunk_x in 0..width / n {
+            for &(flip_x, flip_y) in [(false, false), (true, false), (false, true), (true, true)].iter() {
+                let mut tiles = Vec::with_capacity(n * n);
+                for y in 0..n {
+                    for x in 0..n {
+                        let sx = if flip_x { n - 1 - x } else { x };
+                        let sy = if flip_y { n - 1 - y } else { y };
+                        tiles.push(at(chunk_x * n + sx, chunk_y * n + sy));
+                    }
+                }
+
+                match patterns.iter_mut().find(|pattern| pattern.tiles == tiles) {
+                    Some(pattern) => pattern.weight += 1,
+                    None => patterns.push(Pattern { tiles, weight: 1 }),
+                }
+            }
+        }
+    }
+    patterns
+}
+
+/// The tiles along one side of a pattern.
+fn edge(tiles: &[TileType], n: usize, direction: usize) -> Vec<TileType> {
+    match direction {
+        0 => tiles[0..n].to_vec(),
+        1 => tiles[(n - 1) * n..n * n].to_vec(),
+        2 => (0..n).map(|row| tiles[row * n + n - 1]).collect(),
+        _ => (0..n).map(|row| tiles[row * n]).collect(),
+    }
+}
+
+fn opposite(direction: usize) -> usize {
+    match direction {
+        0 => 1,
+        1 => 0,
+        2 => 3,
+        _ => 2,
+    }
+}
@@ -1,15 +1,26 @@
-use crate::components::{BlocksTile, Position, Render};
-use crate::map::{Map, TileType};
+use crate::components::{BlocksTile, CombatStats, Name, Player, Position, Render};
+use crate::config::InputConfig;
+use crate::dijkstra::{DijkstraMap, PlayerDistanceMap};
+use crate::gamelog::MessageLog;
+use crate::map::{Depth, Map, RoomTheme};
+use crate::map_builders;
+use crate::rng::MapSeed;
+use crate::terrain::TerrainTable;
 use bevy::prelude::*;
 use bevy_tilemap::prelude::*;
 
 pub fn map_indexing(
     mut map_data: ResMut<Map>,
-    position_query: Query<(Entity, &Position, Option<&BlocksTile>)>,
+    terrain: Res<TerrainTable>,
+    position_query: Query<(Entity, &Position, Option<&BlocksTile>, Option<&CombatStats>)>,
 ) {
-    map_data.populate_blocked();
+    map_data.populate_blocked(&terrain);
     map_data.clear_content_index();
-    for (entity, position, blocks_tile) in position_query.iter() {
+    for (entity, position, blocks_tile, combat_stats) in position_query.iter() {
+        // The dead are only despawned once this turn's systems have run
+        if combat_stats.map_or(false, |stats| stats.hp <= 0) {
+            continue;
+        }
         let idx = map_data.xy_idx(position.x, position.y);
 
         if let Some(_p) = blocks_tile {
@@ -20,25 +31,68 @@ pub fn map_indexing(
     }
 }
 
-pub fn draw_map(map_data: Res<Map>, mut tilemap_query: Query<&mut Tilemap>, render_query: Query<(&Position, &Render)>) {
+/// How far from the player, in movement cost, monsters can find their way using the player's
+/// distance map.
+const PLAYER_MAP_DEPTH: f32 = 40.0;
+
+pub fn player_distance_map(
+    map_data: Res<Map>,
+    terrain: Res<TerrainTable>,
+    mut distance_map: ResMut<PlayerDistanceMap>,
+    player_query: Query<&Position, With<Player>>,
+) {
+    let position = player_query.single().expect("There should only be one player");
+    let idx = map_data.xy_idx(position.x, position.y);
+    distance_map.0 = DijkstraMap::new(&map_data, &terrain, &[idx], PLAYER_MAP_DEPTH);
+}
+
+/// Blanks the map layer wherever the player hasn't seen yet, after something showed the whole
+/// level.
+pub fn hide_unrevealed(tilemap: &mut Tilemap, map_data: &Map) {
+    for idx in 0..map_data.tiles.len() {
+        if !map_data.revealed_tiles[idx] {
+            let mut tile = tilemap
+                .get_tile_mut(map_data.idx_xy(idx), 0)
+                .expect("Nonexistent Tile");
+            tile.index = ' ' as usize;
+        }
+    }
+}
+
+/// Says what kind of room the player has walked into, the first time they go in.
+pub fn announce_rooms(
+    mut map_data: ResMut<Map>,
+    mut log: ResMut<MessageLog>,
+    player_query: Query<&Position, With<Player>>,
+) {
+    let position = player_query.single().expect("There should only be one player");
+    let map_data = &mut *map_data;
+    for (room, info) in map_data.rooms.iter().zip(map_data.room_info.iter_mut()) {
+        if room.contains(position.x, position.y) && !info.entered {
+            info.entered = true;
+            if info.theme != RoomTheme::Plain {
+                log.add(format!("You enter a {}.", info.theme.name()));
+            }
+        }
+    }
+}
+
+pub fn draw_map(
+    map_data: Res<Map>,
+    terrain: Res<TerrainTable>,
+    mut tilemap_query: Query<&mut Tilemap>,
+    render_query: Query<(&Position, &Render, Option<&CombatStats>)>,
+) {
     let mut tilemap = tilemap_query
         .single_mut()
         .expect("There should only be one map");
 
     for (idx, tile) in map_data.tiles.iter().enumerate() {
         if map_data.revealed_tiles[idx] {
-            let sprite_idx;
-            let mut color;
-            match tile {
-                TileType::Floor => {
-                    sprite_idx = '.' as usize;
-                    color = Color::rgb_linear(0.0, 0.5, 0.5);
-                }
-                TileType::Wall => {
-                    sprite_idx = '#' as usize;
-                    color = Color::rgb_linear(0.0, 1.0, 0.0);
-                }
-            }
+            let terrain_def = terrain.get(*tile);
+            let sprite_idx = terrain_def.glyph as usize;
+            let (r, g, b) = terrain_def.color;
+            let mut color = Color::rgb_linear(r, g, b);
             if !map_data.visible_tiles[idx] {
                 let gray = color.r() * 0.2126 + color.g() * 0.7152 + color.b() * 0.0722;
                 color = Color::rgb_linear(gray, gray, gray);
@@ -54,7 +108,11 @@ pub fn draw_map(map_data: Res<Map>, mut tilemap_query: Query<&mut Tilemap>, rend
     }
 
     let mut tiles = Vec::new();
-    for (position, render) in render_query.iter() {
+    for (position, render, combat_stats) in render_query.iter() {
+        // The dead are only despawned once this turn's systems have run, and mustn't be redrawn
+        if combat_stats.map_or(false, |stats| stats.hp <= 0) {
+            continue;
+        }
         let idx = map_data.xy_idx(position.x, position.y);
         if map_data.visible_tiles[idx] {
             if !tilemap.get_tile((position.x, position.y), render.sprite_order).is_some() {
@@ -66,8 +124,39 @@ pub fn draw_map(map_data: Res<Map>, mut tilemap_query: Query<&mut Tilemap>, rend
                 })
             }
         } else {
-            tilemap.clear_tile((position.x, position.y), 2).unwrap();
+            tilemap.clear_tile((position.x, position.y), render.sprite_order).unwrap();
         }
     }
     tilemap.insert_tiles(tiles).unwrap();
 }
+
+/// Writes the current level to `assets/maps` when the dump key is pressed, so it can be attached
+/// to a bug report and played again with `--map`.
+pub fn dump_map(
+    keyboard_input: Res<Input<KeyCode>>,
+    input_config: Res<InputConfig>,
+    map_data: Res<Map>,
+    depth: Res<Depth>,
+    seed: Res<MapSeed>,
+    player_query: Query<&Position, With<Player>>,
+    name_query: Query<(&Position, &Name), Without<Player>>,
+) {
+    if !keyboard_input.just_pressed(input_config.dump_map) {
+        return;
+    }
+    let player = match player_query.single() {
+        Ok(position) => position,
+        Err(_) => return,
+    };
+
+    let spawns: Vec<_> = name_query
+        .iter()
+        .map(|(position, name)| ((position.x, position.y), name.name.clone()))
+        .collect();
+    let text = map_builders::to_ascii(&map_data, (player.x, player.y), &spawns);
+
+    let name = format!("dump-{}-{}", seed.0, depth.0);
+    std::fs::create_dir_all("assets/maps").unwrap();
+    std::fs::write(format!("assets/maps/{}.txt", name), text).expect("Unable to write map dump");
+    info!("Dumped the level, play it again with --map {}", name);
+}
@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use bevy_tilemap::point::Point2;
+use bevy_tilemap::prelude::*;
+use rand::prelude::*;
+
+use crate::character::move_sprite;
+use crate::combat_system::WantsToMelee;
+use crate::components::{CombatStats, Confused, Energy, Monster, Name, Player, Position, Render, Viewshed};
+use crate::dijkstra::PlayerDistanceMap;
+use crate::energy_system::{move_cost, Action, ACTION_THRESHOLD};
+use crate::gamelog::MessageLog;
+use crate::map::{Map, ORTHOGONAL_DIRECTIONS};
+use crate::rng::GameRng;
+use crate::terrain::TerrainTable;
+
+/// Monsters move like the player does, so they can't slip past diagonally.
+const MONSTERS_MOVE_DIAGONALLY: bool = false;
+
+/// What a monster's turn looks at and changes.
+type MonsterState = (
+    Entity,
+    &'static mut Monster,
+    &'static mut Position,
+    &'static mut Viewshed,
+    &'static mut Energy,
+    &'static Render,
+    &'static CombatStats,
+    &'static Name,
+    Option<&'static mut Confused>,
+);
+
+/// Each monster with enough energy to act that knows where the player is closes in, and attacks
+/// when next to them. Badly hurt monsters that can see the player run away instead, and
+/// confused ones stumble about.
+#[allow(clippy::too_many_arguments)]
+pub fn monster_ai(
+    mut commands: Commands,
+    mut map_data: ResMut<Map>,
+    terrain: Res<TerrainTable>,
+    mut rng: ResMut<GameRng>,
+    mut log: ResMut<MessageLog>,
+    distance_map: Res<PlayerDistanceMap>,
+    mut melee_events: EventWriter<WantsToMelee>,
+    mut tilemap_query: Query<&mut Tilemap>,
+    player_query: Query<(Entity, &Position), With<Player>>,
+    mut monster_query: Query<MonsterState, Without<Player>>,
+) {
+    let mut tilemap = tilemap_query
+        .single_mut()
+        .expect("There should only be one map");
+    let (player, player_position) = player_query.single().expect("There should only be one player");
+    // Only worked out once a monster needs it, as it covers the whole map
+    let mut flee_map = None;
+
+    for (entity, mut monster, mut position, mut viewshed, mut energy, render, stats, name, confused) in
+        monster_query.iter_mut()
+    {
+        if stats.hp <= 0 || energy.amount < ACTION_THRESHOLD {
+            continue;
+        }
+
+        let idx = map_data.xy_idx(position.x, position.y);
+        if let Some(mut confused) = confused {
+            confused.turns -= 1;
+            if confused.turns <= 0 {
+                commands.entity(entity).remove::<Confused>();
+                log.add(format!("{} is no longer confused.", name.name));
+            }
+            let (dx, dy) = *ORTHOGONAL_DIRECTIONS.choose(&mut rng.0).unwrap();
+            let (x, y) = (position.x + dx, position.y + dy);
+            if (x, y) == (player_position.x, player_position.y) || !map_data.is_walkable(x, y) {
+                energy.amount -= Action::Wait.cost();
+                continue;
+            }
+            step_to(&mut map_data, &mut tilemap, idx, (x, y), &mut position, render);
+            viewshed.dirty = true;
+            energy.amount -= move_cost(&terrain, map_data.tiles[map_data.xy_idx(x, y)]);
+            continue;
+        }
+
+        let sees_player = viewshed
+            .visible_tiles
+            .contains(&Point2::new(player_position.x, player_position.y));
+        if sees_player {
+            monster.last_seen_player = Some(*player_position);
+        }
+
+        let distance = (player_position.x - position.x).abs() + (player_position.y - position.y).abs();
+        if sees_player && distance == 1 {
+            melee_events.send(WantsToMelee {
+                attacker: entity,
+                target: player,
+            });
+            energy.amount -= Action::Attack.cost();
+            continue;
+        }
+
+        let step = if sees_player && stats.hp * 4 <= stats.max_hp {
+            flee_map
+                .get_or_insert_with(|| distance_map.0.flee_map())
+                .lowest_neighbour(idx)
+                .map(|next| map_data.idx_xy(next))
+        } else if sees_player {
+            // The player's distance map is cheaper than a search, but only reaches so far
+            distance_map
+                .0
+                .lowest_neighbour(idx)
+                .map(|next| map_data.idx_xy(next))
+                .or_else(|| first_step(&map_data, &position, *player_position))
+        } else if let Some(target) = monster.last_seen_player {
+            if (target.x, target.y) == (position.x, position.y) {
+                // Nothing here any more, so give up the chase
+                monster.last_seen_player = None;
+                None
+            } else {
+                first_step(&map_data, &position, target)
+            }
+        } else {
+            None
+        };
+
+        let step = step.filter(|&(x, y)| {
+            (x, y) != (player_position.x, player_position.y) && !map_data.blocked[map_data.xy_idx(x, y)]
+        });
+        match step {
+            Some(step) => {
+                step_to(&mut map_data, &mut tilemap, idx, step, &mut position, render);
+                viewshed.dirty = true;
+                energy.amount -= move_cost(&terrain, map_data.tiles[map_data.xy_idx(step.0, step.1)]);
+            }
+            None => energy.amount -= Action::Wait.cost(),
+        }
+    }
+}
+
+/// Moves a monster from `idx` to `(x, y)`. Keeps `blocked` up to date so monsters moving later
+/// this turn don't pile into one tile.
+fn step_to(map_data: &mut Map, tilemap: &mut Tilemap, idx: usize, (x, y): (i32, i32), position: &mut Position, render: &Render) {
+    let next = map_data.xy_idx(x, y);
+    map_data.blocked[idx] = false;
+    map_data.blocked[next] = true;
+    let previous_position = *position;
+    *position = Position { x, y };
+    move_sprite(tilemap, previous_position, *position, render);
+}
+
+/// The first step along a path from `position` to `target`.
+fn first_step(map_data: &Map, position: &Position, target: Position) -> Option<(i32, i32)> {
+    map_data
+        .find_path((position.x, position.y), (target.x, target.y), MONSTERS_MOVE_DIAGONALLY)
+        .and_then(|path| path.first().copied())
+}
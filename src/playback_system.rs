@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use bevy_tilemap::prelude::*;
+
+use crate::config::InputConfig;
+use crate::map::{Map, TileType};
+use crate::map_system::hide_unrevealed;
+use crate::terrain::TerrainTable;
+use crate::GameState;
+
+/// Snapshots of the level being built, waiting to be shown before play starts.
+#[derive(Default)]
+pub struct MapgenPlayback {
+    pub frames: Vec<Vec<TileType>>,
+}
+
+/// Shows one snapshot each time the tilemap's timer fires, then hands over to the player.
+#[allow(clippy::too_many_arguments)]
+pub fn play_back(
+    mut game_state: ResMut<State<GameState>>,
+    mut playback: ResMut<MapgenPlayback>,
+    keyboard_input: Res<Input<KeyCode>>,
+    input_config: Res<InputConfig>,
+    time: Res<Time>,
+    map_data: Res<Map>,
+    terrain: Res<TerrainTable>,
+    mut tilemap_query: Query<(&mut Tilemap, &mut Timer)>,
+) {
+    let (mut tilemap, mut timer) = tilemap_query
+        .single_mut()
+        .expect("There should only be one map");
+
+    if playback.frames.is_empty() || keyboard_input.just_pressed(input_config.skip_playback) {
+        playback.frames.clear();
+        hide_unrevealed(&mut tilemap, &map_data);
+        game_state.set(GameState::PlayerTurn).unwrap();
+        return;
+    }
+
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let frame = playback.frames.remove(0);
+    for (idx, tile) in frame.iter().enumerate() {
+        let terrain_def = terrain.get(*tile);
+        let (r, g, b) = terrain_def.color;
+        let mut map_tile = tilemap
+            .get_tile_mut(map_data.idx_xy(idx), 0)
+            .expect("Nonexistent Tile");
+        map_tile.index = terrain_def.glyph as usize;
+        map_tile.color = Color::rgb_linear(r, g, b);
+    }
+}
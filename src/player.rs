@@ -1,66 +1,194 @@
 use bevy::prelude::*;
 use bevy_tilemap::prelude::*;
+use rand::prelude::*;
 
 use crate::GameState;
-use crate::components::Viewshed;
+use crate::combat_system::WantsToMelee;
+use crate::components::{CombatStats, Confused, Energy, InBackpack, Item, Monster, Name, Viewshed};
+use crate::energy_system::{move_cost, Action};
 use crate::config::InputConfig;
-use crate::map::Map;
+use crate::gamelog::MessageLog;
+use crate::inventory_system::InventoryMenu;
+use crate::map::{Map, TileType, ORTHOGONAL_DIRECTIONS};
+use crate::rng::GameRng;
+use crate::terrain::TerrainTable;
 use crate::{
     character::move_sprite,
     components::{Player, Position, Render},
 };
 
+/// What the player's turn looks at and changes.
+type PlayerState = (
+    Entity,
+    &'static mut Position,
+    &'static Render,
+    &'static Player,
+    &'static mut Viewshed,
+    &'static mut Energy,
+    Option<&'static mut Confused>,
+);
+
+#[allow(clippy::too_many_arguments)]
 pub fn character_movement(
+    mut commands: Commands,
     mut gamestate: ResMut<State<GameState>>,
     keyboard_input: Res<Input<KeyCode>>,
     input_config: Res<InputConfig>,
-    map_data: Res<Map>,
+    mut map_data: ResMut<Map>,
+    terrain: Res<TerrainTable>,
+    mut log: ResMut<MessageLog>,
+    mut inventory_menu: ResMut<InventoryMenu>,
+    mut rng: ResMut<GameRng>,
+    mut melee_events: EventWriter<WantsToMelee>,
     mut map_query: Query<&mut Tilemap>,
-    mut player_query: Query<(&mut Position, &Render, &Player, &mut Viewshed)>,
+    mut player_query: Query<PlayerState>,
+    hostile_query: Query<Entity, (With<Monster>, With<CombatStats>)>,
+    item_query: Query<(&Name, &Render), With<Item>>,
+    backpack_query: Query<&InBackpack>,
 ) {
-    let mut moved = false;
+    let mut took_turn = false;
     for mut map in map_query.iter_mut() {
-        for (mut position, render, _player, mut viewshed) in player_query.iter_mut() {
+        for (entity, mut position, render, _player, mut viewshed, mut energy, mut confused) in player_query.iter_mut() {
             for key in keyboard_input.get_just_pressed() {
                 let previous_position = *position;
 
-                if key == &input_config.up {
-                    if try_move_player(&map_data, &mut position, (0, 1)) {
-                        viewshed.dirty = true;
-                    }
+                let delta_xy = if key == &input_config.up {
+                    Some((0, 1))
                 } else if key == &input_config.left {
-                    if try_move_player(&map_data, &mut position, (-1, 0)) {
-                        viewshed.dirty = true;
-                    }
+                    Some((-1, 0))
                 } else if key == &input_config.down {
-                    if try_move_player(&map_data, &mut position, (0, -1)) {
+                    Some((0, -1))
+                } else if key == &input_config.right {
+                    Some((1, 0))
+                } else {
+                    None
+                };
+
+                let mut action = None;
+                if let Some(mut delta_xy) = delta_xy {
+                    if confused.is_some() {
+                        delta_xy = *ORTHOGONAL_DIRECTIONS.choose(&mut rng.0).unwrap();
+                    }
+                    if try_attack(&map_data, &hostile_query, &mut melee_events, entity, &position, delta_xy) {
+                        action = Some(Action::Attack);
+                    } else if try_move_player(&mut map_data, &terrain, &mut position, delta_xy) {
                         viewshed.dirty = true;
+                        action = Some(Action::Move);
                     }
-                } else if key == &input_config.right {
-                    if try_move_player(&map_data, &mut position, (1, 0)) {
+                } else if key == &input_config.descend {
+                    if map_data.tiles[map_data.xy_idx(position.x, position.y)] == TileType::DownStairs {
+                        gamestate.set(GameState::NextLevel).unwrap();
+                        return;
+                    }
+                } else if key == &input_config.ascend {
+                    if map_data.tiles[map_data.xy_idx(position.x, position.y)] == TileType::UpStairs {
+                        gamestate.set(GameState::PreviousLevel).unwrap();
+                        return;
+                    }
+                } else if key == &input_config.inventory {
+                    inventory_menu.dropping = false;
+                    gamestate.set(GameState::ShowInventory).unwrap();
+                    return;
+                } else if key == &input_config.editor {
+                    gamestate.set(GameState::Editor).unwrap();
+                    return;
+                } else if key == &input_config.close_door {
+                    if try_close_door(&mut map_data, &terrain, &position) {
                         viewshed.dirty = true;
+                        action = Some(Action::CloseDoor);
+                    }
+                } else if key == &input_config.pick_up {
+                    if try_pick_up(&mut commands, &map_data, &mut log, &item_query, &mut map, entity, &position) {
+                        action = Some(Action::PickUp);
+                    }
+                } else if key == &input_config.drop {
+                    // The drop itself spends the turn once an item is picked from the inventory
+                    if backpack_query.iter().any(|backpack| backpack.owner == entity) {
+                        inventory_menu.dropping = true;
+                        gamestate.set(GameState::ShowInventory).unwrap();
+                        return;
                     }
+                    log.add("You have nothing to drop.");
                 }
 
                 if previous_position != *position {
                     move_sprite(&mut map, previous_position, *position, render);
-                    viewshed.dirty = true;
-                    moved = true;
+                }
+                if let Some(action) = action {
+                    energy.amount -= match action {
+                        // Opening a door is a move that stays put, so only a real step pays for terrain
+                        Action::Move if previous_position != *position => {
+                            move_cost(&terrain, map_data.tiles[map_data.xy_idx(position.x, position.y)])
+                        }
+                        _ => action.cost(),
+                    };
+                    if let Some(confused) = confused.as_mut() {
+                        confused.turns -= 1;
+                        if confused.turns <= 0 {
+                            commands.entity(entity).remove::<Confused>();
+                            log.add("You are no longer confused.");
+                        }
+                    }
+                    took_turn = true;
+                    // One action per turn, even if several keys went down at once
+                    break;
                 }
             }
-
         }
     }
-    if moved == true {
+    if took_turn == true {
         gamestate.set(GameState::PlayerTurn).unwrap();
     }
 
 }
 
-pub fn try_move_player(map_data: &Map, position: &mut Position, delta_xy: (i32, i32)) -> bool {
+/// Attacks whatever hostile is standing where the player is moving. Returns whether there was
+/// one, in which case the player doesn't move.
+fn try_attack(
+    map_data: &Map,
+    hostile_query: &Query<Entity, (With<Monster>, With<CombatStats>)>,
+    melee_events: &mut EventWriter<WantsToMelee>,
+    player: Entity,
+    position: &Position,
+    delta_xy: (i32, i32),
+) -> bool {
+    let (x, y) = (position.x + delta_xy.0, position.y + delta_xy.1);
+    if !map_data.in_bounds(x, y) {
+        return false;
+    }
+    let target = map_data.tile_content[map_data.xy_idx(x, y)]
+        .iter()
+        .copied()
+        .find(|&entity| hostile_query.get(entity).is_ok());
+    match target {
+        Some(target) => {
+            melee_events.send(WantsToMelee {
+                attacker: player,
+                target,
+            });
+            true
+        }
+        None => false,
+    }
+}
+
+/// Moves the player, or opens a closed door in the way. Returns whether that spent the turn.
+pub fn try_move_player(
+    map_data: &mut Map,
+    terrain: &TerrainTable,
+    position: &mut Position,
+    delta_xy: (i32, i32),
+) -> bool {
     let new_x = position.x + delta_xy.0;
     let new_y = position.y + delta_xy.1;
-    if !map_data.blocked[map_data.xy_idx(new_x, new_y)] {
+    if !map_data.in_bounds(new_x, new_y) {
+        return false;
+    }
+    let idx = map_data.xy_idx(new_x, new_y);
+    if map_data.tiles[idx] == TileType::ClosedDoor {
+        map_data.set_tile(idx, TileType::OpenDoor, terrain);
+        true
+    } else if !map_data.blocked[idx] {
         position.x = new_x;
         position.y = new_y;
         true
@@ -68,3 +196,56 @@ pub fn try_move_player(map_data: &Map, position: &mut Position, delta_xy: (i32,
         false
     }
 }
+
+/// Closes an open door next to the player, as long as nothing is in the doorway. That includes
+/// items lying there as well as anyone standing in it, so a dropped item can wedge a door open.
+pub fn try_close_door(map_data: &mut Map, terrain: &TerrainTable, position: &Position) -> bool {
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let (x, y) = (position.x + dx, position.y + dy);
+            if map_data.get(x, y) != Some(TileType::OpenDoor) {
+                continue;
+            }
+            let idx = map_data.xy_idx(x, y);
+            if map_data.tile_content[idx].is_empty() {
+                map_data.set_tile(idx, TileType::ClosedDoor, terrain);
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Picks up an item lying where the player stands. Returns whether there was one.
+fn try_pick_up(
+    commands: &mut Commands,
+    map_data: &Map,
+    log: &mut MessageLog,
+    item_query: &Query<(&Name, &Render), With<Item>>,
+    tilemap: &mut Tilemap,
+    player: Entity,
+    position: &Position,
+) -> bool {
+    let idx = map_data.xy_idx(position.x, position.y);
+    let item = map_data.tile_content[idx]
+        .iter()
+        .find_map(|&entity| item_query.get(entity).ok().map(|(name, render)| (entity, name, render)));
+    match item {
+        Some((item, name, render)) => {
+            // Without a position it's no longer on the level, so nothing draws or stores it
+            commands
+                .entity(item)
+                .remove::<Position>()
+                .insert(InBackpack { owner: player });
+            tilemap
+                .clear_tile((position.x, position.y), render.sprite_order)
+                .unwrap();
+            log.add(format!("You pick up the {}.", name.name));
+            true
+        }
+        None => {
+            log.add("There is nothing here to pick up.");
+            false
+        }
+    }
+}
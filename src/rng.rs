@@ -0,0 +1,23 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+/// The seed the current run was started with.
+#[derive(Copy, Clone)]
+pub struct MapSeed(pub u64);
+
+impl MapSeed {
+    /// Each level gets its own generator so it comes out the same no matter what
+    /// happened on the levels before it.
+    pub fn level_rng(&self, depth: i32) -> StdRng {
+        StdRng::seed_from_u64(self.0 ^ (depth as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+}
+
+/// Every random choice made during play should be drawn from this so a run can be reproduced
+/// from its `MapSeed`.
+pub struct GameRng(pub StdRng);
+
+impl GameRng {
+    pub fn from_seed(seed: MapSeed) -> Self {
+        Self(StdRng::seed_from_u64(seed.0))
+    }
+}
@@ -133,7 +133,6 @@ where
     ///
     /// The function **obstruct(x, y)** should return a float indicating
     /// the opacity in 0. to 1. of the square at coordinates **x, y**.
-    #[allow(dead_code)]
     pub fn new(radius: i32, obstruct: F) -> Self {
         RPAPartialShadowcasting {
             obstruct: obstruct,
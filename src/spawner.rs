@@ -0,0 +1,197 @@
+use bevy::prelude::*;
+use bevy_tilemap::prelude::*;
+
+use crate::components::{
+    AreaOfEffect, BlocksTile, CombatStats, Confusion, Consumable, DefenseBonus, EnemyBundle, Energy, EquipmentSlot,
+    Equippable, InflictsDamage, Item, ItemBundle, MeleePowerBonus, Monster, Name, Player, PlayerBundle, Position,
+    ProvidesHealing, Ranged, Render, Speed, SufferDamage, Viewshed,
+};
+
+pub fn player(commands: &mut Commands, tiles: &mut Vec<Tile>, (x, y): (i32, i32)) {
+    let render = Render {
+        sprite_index: '@' as usize,
+        sprite_order: 2,
+        tint: Color::GREEN,
+    };
+    tiles.push(tile_for(&render, x, y));
+
+    commands.spawn().insert_bundle(PlayerBundle {
+        player: Player,
+        name: Name {
+            name: "Player".to_string(),
+        },
+        position: Position { x, y },
+        render,
+        viewshed: Viewshed {
+            visible_tiles: Vec::new(),
+            range: 8,
+            dirty: true,
+        },
+        combat_stats: CombatStats {
+            max_hp: 30,
+            hp: 30,
+            defense: 2,
+            power: 5,
+        },
+        suffer_damage: SufferDamage::default(),
+        energy: Energy::default(),
+        speed: Speed { energy_per_tick: 100 },
+    });
+}
+
+/// Spawns something asked for by name, such as an entry in a prefab's legend.
+pub fn named(commands: &mut Commands, tiles: &mut Vec<Tile>, name: &str, point: (i32, i32)) {
+    match name {
+        "Goblin" => goblin(commands, tiles, point),
+        "Orc" => orc(commands, tiles, point),
+        "Health Potion" => health_potion(commands, tiles, point),
+        "Magic Missile Scroll" => magic_missile_scroll(commands, tiles, point),
+        "Fireball Scroll" => fireball_scroll(commands, tiles, point),
+        "Confusion Scroll" => confusion_scroll(commands, tiles, point),
+        "Dagger" => dagger(commands, tiles, point),
+        "Shield" => shield(commands, tiles, point),
+        _ => warn!("Don't know how to spawn {}", name),
+    }
+}
+
+pub fn goblin(commands: &mut Commands, tiles: &mut Vec<Tile>, point: (i32, i32)) {
+    let stats = CombatStats {
+        max_hp: 8,
+        hp: 8,
+        defense: 1,
+        power: 3,
+    };
+    monster(commands, tiles, point, "Goblin", monster_render('g', Color::RED), stats, 120);
+}
+
+pub fn orc(commands: &mut Commands, tiles: &mut Vec<Tile>, point: (i32, i32)) {
+    let stats = CombatStats {
+        max_hp: 16,
+        hp: 16,
+        defense: 1,
+        power: 4,
+    };
+    monster(commands, tiles, point, "Orc", monster_render('o', Color::ORANGE_RED), stats, 80);
+}
+
+fn monster(
+    commands: &mut Commands,
+    tiles: &mut Vec<Tile>,
+    (x, y): (i32, i32),
+    name: &str,
+    render: Render,
+    combat_stats: CombatStats,
+    energy_per_tick: i32,
+) {
+    tiles.push(tile_for(&render, x, y));
+
+    commands.spawn_bundle(EnemyBundle {
+        monster: Monster::default(),
+        name: Name { name: name.to_string() },
+        position: Position { x, y },
+        render,
+        viewshed: Viewshed {
+            visible_tiles: Vec::new(),
+            range: 8,
+            dirty: true,
+        },
+        blocks_tile: BlocksTile,
+        combat_stats,
+        suffer_damage: SufferDamage::default(),
+        energy: Energy::default(),
+        speed: Speed { energy_per_tick },
+    });
+}
+
+/// Monsters go on the actor layer, like the player.
+fn monster_render(glyph: char, tint: Color) -> Render {
+    Render {
+        sprite_index: glyph as usize,
+        sprite_order: 2,
+        tint,
+    }
+}
+
+pub fn health_potion(commands: &mut Commands, tiles: &mut Vec<Tile>, point: (i32, i32)) {
+    let potion = item(commands, tiles, point, "Health Potion", '!', Color::FUCHSIA);
+    commands
+        .entity(potion)
+        .insert(ProvidesHealing { amount: 8 })
+        .insert(Consumable);
+}
+
+pub fn magic_missile_scroll(commands: &mut Commands, tiles: &mut Vec<Tile>, point: (i32, i32)) {
+    let scroll = item(commands, tiles, point, "Magic Missile Scroll", '?', Color::CYAN);
+    commands
+        .entity(scroll)
+        .insert(Ranged { range: 6 })
+        .insert(InflictsDamage { damage: 8 })
+        .insert(Consumable);
+}
+
+pub fn fireball_scroll(commands: &mut Commands, tiles: &mut Vec<Tile>, point: (i32, i32)) {
+    let scroll = item(commands, tiles, point, "Fireball Scroll", '?', Color::ORANGE);
+    commands
+        .entity(scroll)
+        .insert(Ranged { range: 6 })
+        .insert(InflictsDamage { damage: 20 })
+        .insert(AreaOfEffect { radius: 3 })
+        .insert(Consumable);
+}
+
+pub fn confusion_scroll(commands: &mut Commands, tiles: &mut Vec<Tile>, point: (i32, i32)) {
+    let scroll = item(commands, tiles, point, "Confusion Scroll", '?', Color::PINK);
+    commands
+        .entity(scroll)
+        .insert(Ranged { range: 6 })
+        .insert(Confusion { turns: 4 })
+        .insert(Consumable);
+}
+
+pub fn dagger(commands: &mut Commands, tiles: &mut Vec<Tile>, point: (i32, i32)) {
+    let dagger = item(commands, tiles, point, "Dagger", '/', Color::CYAN);
+    commands
+        .entity(dagger)
+        .insert(Equippable {
+            slot: EquipmentSlot::Melee,
+        })
+        .insert(MeleePowerBonus { power: 2 });
+}
+
+pub fn shield(commands: &mut Commands, tiles: &mut Vec<Tile>, point: (i32, i32)) {
+    let shield = item(commands, tiles, point, "Shield", '[', Color::CYAN);
+    commands
+        .entity(shield)
+        .insert(Equippable {
+            slot: EquipmentSlot::Shield,
+        })
+        .insert(DefenseBonus { defense: 1 });
+}
+
+fn item(commands: &mut Commands, tiles: &mut Vec<Tile>, (x, y): (i32, i32), name: &str, glyph: char, tint: Color) -> Entity {
+    // Items go on the layer under actors, so whoever stands on one hides it
+    let render = Render {
+        sprite_index: glyph as usize,
+        sprite_order: 1,
+        tint,
+    };
+    tiles.push(tile_for(&render, x, y));
+
+    commands
+        .spawn_bundle(ItemBundle {
+            item: Item,
+            name: Name { name: name.to_string() },
+            position: Position { x, y },
+            render,
+        })
+        .id()
+}
+
+fn tile_for(render: &Render, x: i32, y: i32) -> Tile {
+    Tile {
+        point: (x, y),
+        sprite_index: render.sprite_index,
+        sprite_order: render.sprite_order,
+        tint: render.tint,
+    }
+}
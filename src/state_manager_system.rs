@@ -1,18 +1,20 @@
 use bevy::prelude::*;
 
-use crate::{GameState, components::GameLog};
+use crate::{GameState, components::{CombatStats, Energy, GameLog, Player, Position, Speed}};
+use crate::energy_system::{self, Ready};
 
 pub fn state_manager(
     mut state: ResMut<State<GameState>>,
     asset_server: Res<AssetServer>,
     mut query: Query<&mut Text, With<GameLog>>,
+    mut actors: Query<(&mut Energy, &Speed, &CombatStats, Option<&Player>), With<Position>>,
 ) {
     match state.current() {
         GameState::AwaitingInput => {
             return;
         },
         GameState::PlayerTurn => {
-            state.set(GameState::AwaitingInput).expect("Unable to change state");
+            state.set(GameState::Ticking).expect("Unable to change state");
             let mut text = query.single_mut().expect("only one gamelog should exist");
 
             /*
@@ -26,7 +28,23 @@ pub fn state_manager(
             });
             */
         }
+        GameState::MonsterTurn => {
+            state.set(GameState::Ticking).expect("Unable to change state");
+        }
+        GameState::Ticking => match energy_system::next_to_act(&mut actors) {
+            Some(Ready::Player) => state.set(GameState::AwaitingInput).expect("Unable to change state"),
+            Some(Ready::Monsters) => state.set(GameState::MonsterTurn).expect("Unable to change state"),
+            // Only happens once the player can't act again, so stop asking
+            None => state.set(GameState::GameOver).expect("Unable to change state"),
+        },
+        GameState::GameOver => {},
         GameState::PreRun => {},
         GameState::Running => {},
+        GameState::NextLevel => {},
+        GameState::PreviousLevel => {},
+        GameState::Editor => {},
+        GameState::ShowingMapgen => {},
+        GameState::ShowInventory => {},
+        GameState::ShowTargeting => {},
     }
 }
\ No newline at end of file
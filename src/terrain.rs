@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::map::TileType;
+
+/// How a kind of terrain looks and behaves, loaded from `assets/config/terrain.ron`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct TerrainDef {
+    pub glyph: char,
+    pub color: (f32, f32, f32),
+    /// Relative cost of stepping onto the tile, 1.0 for plain floor.
+    pub move_cost: f32,
+    pub blocks: bool,
+    /// How much the tile hides what is behind it, from 0.0 to 1.0.
+    pub opacity: f32,
+}
+
+#[derive(Deserialize)]
+pub struct TerrainTable(pub HashMap<TileType, TerrainDef>);
+
+impl TerrainTable {
+    pub fn get(&self, tile: TileType) -> &TerrainDef {
+        self.0
+            .get(&tile)
+            .unwrap_or_else(|| panic!("No terrain defined for {:?}", tile))
+    }
+}
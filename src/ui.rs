@@ -1,10 +1,15 @@
 use bevy::prelude::*;
 
-use crate::{UI_WIDTH, components::GameLog, config::InputConfig};
+use crate::{UI_WIDTH, components::{DepthDisplay, GameLog}, config::InputConfig, gamelog::MessageLog, map::Depth, rng::MapSeed};
+
+/// How many of the latest messages fit in the sidebar.
+const LOG_LINES: usize = 20;
 
 pub fn setup_ui(
     mut commands: Commands,
     input_config: Res<InputConfig>,
+    seed: Res<MapSeed>,
+    depth: Res<Depth>,
     asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
@@ -74,8 +79,9 @@ pub fn setup_ui(
                                                 color: Color::WHITE,
                                             },
                                         },
+                                        // Filled in by update_log
                                         TextSection {
-                                            value: "(With multiple sections!)\n".to_string(),
+                                            value: String::new(),
                                             style: TextStyle {
                                                 font: font.clone(),
                                                 font_size: 12.0,
@@ -98,29 +104,65 @@ pub fn setup_ui(
                                     },
                                     ..Default::default()
                                 },
-                                text: Text::with_section(
-                                    format!(
-                                        "{:?}{:?}{:?}{:?}: Movement\n{:?}: Inventory\n{:?}: Pickup",
-                                        input_config.up,
-                                        input_config.left,
-                                        input_config.down,
-                                        input_config.right,
-                                        input_config.inventory,
-                                        input_config.pick_up,
-                                    ),
-                                    TextStyle {
-                                        font: font.clone(),
-                                        font_size: 12.0,
-                                        color: Color::WHITE,
-                                    },
-                                    TextAlignment {
+                                text: Text {
+                                    sections: vec![
+                                        TextSection {
+                                            value: format!("Depth: {}\n", depth.0),
+                                            style: TextStyle {
+                                                font: font.clone(),
+                                                font_size: 12.0,
+                                                color: Color::WHITE,
+                                            },
+                                        },
+                                        TextSection {
+                                            value: format!(
+                                                "Seed: {}\n{:?}{:?}{:?}{:?}: Movement\n{:?}: Inventory\n{:?}: Pickup\n{:?}: Drop\n{:?}/{:?}: Descend/Ascend\n{:?}: Close door",
+                                                seed.0,
+                                                input_config.up,
+                                                input_config.left,
+                                                input_config.down,
+                                                input_config.right,
+                                                input_config.inventory,
+                                                input_config.pick_up,
+                                                input_config.drop,
+                                                input_config.descend,
+                                                input_config.ascend,
+                                                input_config.close_door,
+                                            ),
+                                            style: TextStyle {
+                                                font: font.clone(),
+                                                font_size: 12.0,
+                                                color: Color::WHITE,
+                                            },
+                                        },
+                                    ],
+                                    alignment: TextAlignment {
                                         horizontal: HorizontalAlign::Left,
                                         ..Default::default()
                                     },
-                                ),
+                                },
                                 ..Default::default()
-                            });
+                            }).insert(DepthDisplay);
                         });
                 });
         });
 }
+
+pub fn update_depth(depth: Res<Depth>, mut query: Query<&mut Text, With<DepthDisplay>>) {
+    if !depth.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!("Depth: {}\n", depth.0);
+    }
+}
+
+pub fn update_log(log: Res<MessageLog>, mut query: Query<&mut Text, With<GameLog>>) {
+    if !log.is_changed() {
+        return;
+    }
+    let start = log.entries.len().saturating_sub(LOG_LINES);
+    for mut text in query.iter_mut() {
+        text.sections[1].value = log.entries[start..].join("\n");
+    }
+}
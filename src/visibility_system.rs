@@ -1,11 +1,13 @@
 use crate::components::{Player, Position, Viewshed};
-use crate::map::{Map, TileType};
-use crate::shadowcasting::RPAShadowcasting;
+use crate::map::Map;
+use crate::shadowcasting::RPAPartialShadowcasting;
+use crate::terrain::TerrainTable;
 use bevy::prelude::*;
 use bevy_tilemap::point::Point2;
 
 pub fn visibility(
     mut map_data: ResMut<Map>,
+    terrain: Res<TerrainTable>,
     mut viewshed_query: Query<(&mut Viewshed, &Position, Option<&Player>)>,
 ) {
     for (mut viewshed, position, player) in viewshed_query.iter_mut() {
@@ -13,22 +15,20 @@ pub fn visibility(
             viewshed.dirty = false;
             viewshed.visible_tiles.clear();
 
-            for (rel_x, rel_y, visible) in RPAShadowcasting::new(viewshed.range, |x, y| {
-                map_data.tiles[map_data.xy_idx(x + position.x, y + position.y)] == TileType::Wall
+            for (rel_x, rel_y, opacity) in RPAPartialShadowcasting::new(viewshed.range, |x, y| {
+                match map_data.get(x + position.x, y + position.y) {
+                    Some(tile) => terrain.get(tile).opacity,
+                    None => 1.0,
+                }
             }) {
-                if visible {
+                if opacity < 1.0 {
                     viewshed
                         .visible_tiles
                         .push(Point2::new(position.x + rel_x, position.y + rel_y));
                 }
             }
 
-            viewshed.visible_tiles.retain(|p| {
-                p.x >= -map_data.width / 2
-                    && p.x < map_data.width / 2 - 1
-                    && p.y >= -map_data.height / 2
-                    && p.y < map_data.height / 2 - 1
-            });
+            viewshed.visible_tiles.retain(|p| map_data.in_bounds(p.x, p.y));
 
             if let Some(_p) = player {
                 for t in map_data.visible_tiles.iter_mut() {